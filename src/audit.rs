@@ -0,0 +1,354 @@
+use crate::storage::JsonStore;
+use crate::tokens;
+use oc_bots_sdk::types::{Chat, UserId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::error;
+
+// Whose funds a transfer moves, `External` is any account outside the bot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Holder {
+    User(UserId),
+    Escrow,
+    External,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum TransferKind {
+    Tip,
+    ToEscrow,
+    FromEscrow,
+    Withdraw,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum AuditStatus {
+    Completed,
+    // A retry the ledger recognised, the original block index is recorded
+    Duplicate,
+    Failed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub timestamp: i64,
+    pub kind: TransferKind,
+    pub from: Holder,
+    pub to: Holder,
+    // ICRC-1 text of the destination account
+    pub destination: String,
+    pub chat: Option<Chat>,
+    pub token: String,
+    // Base units sent to the destination, the fee is charged on top
+    pub amount: u128,
+    pub fee: u128,
+    pub block_index: Option<u64>,
+    // Hex of the ICRC-1 memo, the same for every attempt at one operation
+    pub memo: String,
+    pub status: AuditStatus,
+}
+
+// What the bot believes a custodial subaccount holds, in base units. Deposits are folded in
+// before every debit, so it only goes negative when the bot sends more than the holder had.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExpectedBalance {
+    pub holder: Holder,
+    pub token: String,
+    pub amount: i128,
+}
+
+// Where a completed transfer went, kept so a retried operation can find its receipt
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletedTransfer {
+    pub to: Holder,
+    pub block_index: u64,
+    pub fee: u128,
+    pub timestamp: i64,
+}
+
+// A debit that took an expected balance below zero
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Overdraft {
+    pub holder: Holder,
+    pub token: String,
+    pub memo: String,
+    // Expected balance left after the debit
+    pub balance: i128,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CustodialBook {
+    balances: Vec<ExpectedBalance>,
+    // Memos already applied to the balances, with when, so a duplicate isn't counted twice
+    applied: HashMap<String, i64>,
+    // Waiting to be reported by the next reconciliation
+    #[serde(default)]
+    overdrafts: Vec<Overdraft>,
+    // Completed transfers by memo, for as long as `applied` remembers them
+    #[serde(default)]
+    completed: HashMap<String, CompletedTransfer>,
+}
+
+impl CustodialBook {
+    // Raise an expected balance to the on-chain one, deposits arrive without going through the bot
+    fn credit_deposits(&mut self, holder: Holder, token: &str, on_chain: i128) {
+        if let Some(balance) = find_balance(&mut self.balances, holder, token) {
+            balance.amount = balance.amount.max(on_chain);
+        }
+    }
+
+    // Move a completed transfer's amount and fee between the expected balances, once per memo
+    fn apply(&mut self, entry: &AuditEntry) {
+        // Anything older than two days is well outside the ledger's deduplication window
+        self.applied.retain(|_, time| entry.timestamp - *time < 2 * 24 * 60 * 60);
+        self.completed.retain(|_, transfer| entry.timestamp - transfer.timestamp < 2 * 24 * 60 * 60);
+        if let Some(block_index) = entry.block_index {
+            self.completed.entry(entry.memo.clone()).or_insert(CompletedTransfer {
+                to: entry.to,
+                block_index,
+                fee: entry.fee,
+                timestamp: entry.timestamp,
+            });
+        }
+        if self.applied.insert(entry.memo.clone(), entry.timestamp).is_some() {
+            return;
+        }
+        if let Some(balance) = find_balance(&mut self.balances, entry.from, &entry.token) {
+            balance.amount -= (entry.amount + entry.fee) as i128;
+            if balance.amount < 0 {
+                let overdraft = Overdraft {
+                    holder: entry.from,
+                    token: entry.token.clone(),
+                    memo: entry.memo.clone(),
+                    balance: balance.amount,
+                    timestamp: entry.timestamp,
+                };
+                error!("Transfer took an expected balance below zero: {:?}", overdraft);
+                self.overdrafts.push(overdraft);
+            }
+        }
+        if let Some(balance) = find_balance(&mut self.balances, entry.to, &entry.token) {
+            balance.amount += entry.amount as i128;
+        }
+    }
+}
+
+// Append-only log of every transfer, plus the custodial balances implied by it
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+    book: JsonStore<CustodialBook>,
+}
+
+impl AuditLog {
+    pub fn new(data_dir: &str) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir, e))?;
+        let path = PathBuf::from(data_dir).join("audit.jsonl");
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            book: JsonStore::open(data_dir, "balances.json")?,
+        })
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit entry {:?}: {}", entry, e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            error!("Failed to append to {:?}: {} ({})", self.path, e, line);
+        }
+
+        if let AuditStatus::Completed | AuditStatus::Duplicate = entry.status {
+            let result = self.book.update(|book| book.apply(&entry));
+            if let Err(e) = result {
+                error!("Failed to update expected balances: {}", e);
+            }
+        }
+    }
+
+    pub fn expected_balances(&self) -> Vec<ExpectedBalance> {
+        self.book.read(|book| book.balances.clone())
+    }
+
+    pub fn expected_balance(&self, holder: Holder, token: &str) -> i128 {
+        self.book.read(|book| {
+            book.balances
+                .iter()
+                .find(|b| b.holder == holder && b.token == token)
+                .map_or(0, |b| b.amount)
+        })
+    }
+
+    // Fold deposits into the expected balance before a debit, given the holder's on-chain balance
+    pub fn credit_deposits(&self, holder: Holder, token: &str, on_chain: i128) -> Result<(), String> {
+        self.book.update(|book| book.credit_deposits(holder, token, on_chain))
+    }
+
+    // Start reconciling a holder for every token, before any transfer involves it
+    pub fn track(&self, holder: Holder) -> Result<(), String> {
+        let tracked = self.book.read(|book| {
+            tokens::TOKENS
+                .iter()
+                .all(|t| book.balances.iter().any(|b| b.holder == holder && b.token == t.symbol))
+        });
+        if tracked {
+            return Ok(());
+        }
+        self.book.update(|book| {
+            for token in tokens::TOKENS {
+                find_balance(&mut book.balances, holder, token.symbol);
+            }
+        })
+    }
+
+    // Raise the expected balance to what's on chain, used when deposits arrive
+    pub fn set_expected_balance(&self, holder: Holder, token: &str, amount: i128) -> Result<(), String> {
+        self.book.update(|book| {
+            if let Some(balance) = find_balance(&mut book.balances, holder, token) {
+                balance.amount = amount;
+            }
+        })
+    }
+
+    // The transfer that completed with a memo, if one did in the last two days
+    pub fn completed(&self, memo: &str) -> Option<CompletedTransfer> {
+        self.book.read(|book| book.completed.get(memo).cloned())
+    }
+
+    // Overdrafts recorded since the last call
+    pub fn take_overdrafts(&self) -> Result<Vec<Overdraft>, String> {
+        self.book.update(|book| std::mem::take(&mut book.overdrafts))
+    }
+}
+
+// Custodial holders get an entry the first time they appear, external accounts aren't tracked
+fn find_balance<'a>(balances: &'a mut Vec<ExpectedBalance>, holder: Holder, token: &str) -> Option<&'a mut ExpectedBalance> {
+    if holder == Holder::External {
+        return None;
+    }
+    let index = match balances.iter().position(|b| b.holder == holder && b.token == token) {
+        Some(index) => index,
+        None => {
+            balances.push(ExpectedBalance { holder, token: token.to_string(), amount: 0 });
+            balances.len() - 1
+        }
+    };
+    Some(&mut balances[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn user(id: u8) -> Holder {
+        Holder::User(UserId::from(Principal::from_slice(&[id])))
+    }
+
+    fn transfer(from: Holder, to: Holder, amount: u128, memo: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp: 1_000,
+            kind: TransferKind::Tip,
+            from,
+            to,
+            destination: String::new(),
+            chat: None,
+            token: "ICP".to_string(),
+            amount,
+            fee: 10,
+            block_index: Some(1),
+            memo: memo.to_string(),
+            status: AuditStatus::Completed,
+        }
+    }
+
+    fn balance(book: &CustodialBook, holder: Holder) -> Option<i128> {
+        book.balances.iter().find(|b| b.holder == holder).map(|b| b.amount)
+    }
+
+    #[test]
+    fn transfers_move_expected_balances() {
+        let mut book = CustodialBook::default();
+        find_balance(&mut book.balances, user(1), "ICP").unwrap().amount = 1_000;
+        book.apply(&transfer(user(1), user(2), 300, "a"));
+        assert_eq!(balance(&book, user(1)), Some(690));
+        assert_eq!(balance(&book, user(2)), Some(300));
+        assert!(book.overdrafts.is_empty());
+    }
+
+    #[test]
+    fn a_retried_memo_is_applied_once() {
+        let mut book = CustodialBook::default();
+        find_balance(&mut book.balances, user(1), "ICP").unwrap().amount = 1_000;
+        book.apply(&transfer(user(1), Holder::Escrow, 300, "a"));
+        book.apply(&transfer(user(1), Holder::Escrow, 300, "a"));
+        assert_eq!(balance(&book, user(1)), Some(690));
+        assert_eq!(balance(&book, Holder::Escrow), Some(300));
+    }
+
+    #[test]
+    fn completed_transfers_are_found_by_memo() {
+        let mut book = CustodialBook::default();
+        let mut entry = transfer(user(1), Holder::Escrow, 300, "a");
+        entry.block_index = Some(42);
+        book.apply(&entry);
+        let completed = book.completed.get("a").unwrap();
+        assert_eq!(completed.block_index, 42);
+        assert_eq!(completed.to, Holder::Escrow);
+        assert!(!book.completed.contains_key("b"));
+
+        // Forgotten once well outside the ledger's deduplication window
+        let mut later = transfer(user(1), user(2), 1, "b");
+        later.timestamp += 3 * 24 * 60 * 60;
+        book.apply(&later);
+        assert!(!book.completed.contains_key("a"));
+    }
+
+    #[test]
+    fn debits_beyond_the_expected_balance_are_flagged() {
+        let mut book = CustodialBook::default();
+        find_balance(&mut book.balances, user(1), "ICP").unwrap().amount = 100;
+        book.apply(&transfer(user(1), Holder::External, 300, "a"));
+        assert_eq!(balance(&book, user(1)), Some(-210));
+        assert_eq!(book.overdrafts.len(), 1);
+        assert_eq!(book.overdrafts[0].balance, -210);
+        // External accounts aren't tracked
+        assert_eq!(balance(&book, Holder::External), None);
+    }
+
+    #[test]
+    fn a_deposit_followed_by_a_tip_is_not_flagged() {
+        let mut book = CustodialBook::default();
+        // 1,000 deposited straight to the subaccount, seen when the tip reads the balance
+        book.credit_deposits(user(1), "ICP", 1_000);
+        book.apply(&transfer(user(1), user(2), 300, "a"));
+        assert_eq!(balance(&book, user(1)), Some(690));
+        assert!(book.overdrafts.is_empty());
+    }
+
+    #[test]
+    fn deposits_never_lower_the_expected_balance() {
+        let mut book = CustodialBook::default();
+        find_balance(&mut book.balances, user(1), "ICP").unwrap().amount = 1_000;
+        // The ledger showing less is a shortfall for reconciliation to report, not a deposit
+        book.credit_deposits(user(1), "ICP", 400);
+        assert_eq!(balance(&book, user(1)), Some(1_000));
+        book.credit_deposits(user(1), "ICP", 1_500);
+        assert_eq!(balance(&book, user(1)), Some(1_500));
+    }
+}
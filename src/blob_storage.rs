@@ -0,0 +1,154 @@
+use candid::{CandidType, Decode, Encode, Principal, Reserved};
+use ic_agent::Agent;
+use oc_bots_sdk::types::BlobReference;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+// Uploaded files are removed by their bucket after this long
+const BLOB_EXPIRY_DAYS: u64 = 30;
+
+#[derive(CandidType)]
+struct AllocatedBucketArgs {
+    file_hash: [u8; 32],
+    file_size: u64,
+    file_id_seed: Option<u128>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct AllocatedBucket {
+    canister_id: Principal,
+    file_id: u128,
+    chunk_size: u32,
+}
+
+#[derive(CandidType, Deserialize)]
+enum AllocatedBucketResponse {
+    Success(AllocatedBucket),
+    AllowanceExceeded(Reserved),
+    UserNotFound,
+    BucketUnavailable,
+}
+
+#[derive(CandidType)]
+struct UploadChunkArgs {
+    file_id: u128,
+    hash: [u8; 32],
+    mime_type: String,
+    accessors: Vec<Principal>,
+    chunk_index: u32,
+    chunk_size: u32,
+    total_size: u64,
+    bytes: ByteBuf,
+    expiry: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum UploadChunkResponse {
+    Success,
+    AllowanceExceeded(Reserved),
+    FileAlreadyExists,
+    FileTooBig(Reserved),
+    FileExpired,
+    ChunkAlreadyExists,
+    ChunkIndexTooHigh,
+    ChunkSizeMismatch(Reserved),
+    Full,
+    HashMismatch(Reserved),
+    InvalidFileId,
+    UserNotFound,
+}
+
+// Files sent into chats are uploaded to OpenChat's storage buckets, allocated by the storage
+// index, and referenced from the message by bucket and file id
+pub struct BlobStorage {
+    agent: Agent,
+    storage_index: Principal,
+}
+
+impl BlobStorage {
+    pub fn new(agent: Agent, storage_index: &str) -> Result<Self, String> {
+        let storage_index = Principal::from_text(storage_index).map_err(|e| format!("Invalid storage index canister: {}", e))?;
+        Ok(Self { agent, storage_index })
+    }
+
+    // Upload a file readable by the given canisters, usually the chat it is sent into
+    pub async fn upload(&self, bytes: &[u8], mime_type: &str, accessors: Vec<Principal>) -> Result<BlobReference, String> {
+        let hash: [u8; 32] = Sha256::digest(bytes).into();
+        let args = AllocatedBucketArgs {
+            file_hash: hash,
+            file_size: bytes.len() as u64,
+            file_id_seed: None,
+        };
+        let response = self
+            .agent
+            .query(&self.storage_index, "allocated_bucket_v2")
+            .with_arg(Encode!(&args).map_err(|e| e.to_string())?)
+            .call()
+            .await
+            .map_err(|e| e.to_string())?;
+        let bucket = match Decode!(&response, AllocatedBucketResponse).map_err(|e| e.to_string())? {
+            AllocatedBucketResponse::Success(bucket) => bucket,
+            AllocatedBucketResponse::AllowanceExceeded(_) => return Err("The bot's file storage allowance is used up".into()),
+            AllocatedBucketResponse::UserNotFound => return Err("The bot has no file storage allowance".into()),
+            AllocatedBucketResponse::BucketUnavailable => return Err("File storage is unavailable, try again later".into()),
+        };
+
+        let expiry = chrono::Utc::now().timestamp_millis() as u64 + BLOB_EXPIRY_DAYS * 24 * 60 * 60 * 1000;
+        for (chunk_index, chunk) in chunks(bytes, bucket.chunk_size).enumerate() {
+            let args = UploadChunkArgs {
+                file_id: bucket.file_id,
+                hash,
+                mime_type: mime_type.to_string(),
+                accessors: accessors.clone(),
+                chunk_index: chunk_index as u32,
+                chunk_size: bucket.chunk_size,
+                total_size: bytes.len() as u64,
+                bytes: ByteBuf::from(chunk.to_vec()),
+                expiry: Some(expiry),
+            };
+            let response = self
+                .agent
+                .update(&bucket.canister_id, "upload_chunk_v2")
+                .with_arg(Encode!(&args).map_err(|e| e.to_string())?)
+                .call_and_wait()
+                .await
+                .map_err(|e| e.to_string())?;
+            match Decode!(&response, UploadChunkResponse).map_err(|e| e.to_string())? {
+                // A retried chunk that already landed is fine
+                UploadChunkResponse::Success | UploadChunkResponse::ChunkAlreadyExists => {}
+                other => return Err(format!("File upload failed: {:?}", other)),
+            }
+        }
+
+        Ok(BlobReference {
+            canister_id: bucket.canister_id.into(),
+            blob_id: bucket.file_id,
+        })
+    }
+}
+
+// An empty file still uploads one empty chunk
+fn chunks(bytes: &[u8], chunk_size: u32) -> impl Iterator<Item = &[u8]> {
+    let chunk_size = (chunk_size as usize).max(1);
+    let empty: &[u8] = &[];
+    let first = if bytes.is_empty() { Some(empty) } else { None };
+    first.into_iter().chain(bytes.chunks(chunk_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_split_at_the_bucket_chunk_size() {
+        let bytes = [1u8; 10];
+        let sizes: Vec<usize> = chunks(&bytes, 4).map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![4, 4, 2]);
+    }
+
+    #[test]
+    fn empty_files_upload_one_chunk() {
+        assert_eq!(chunks(&[], 4).count(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::price_history::Ohlc;
+use chrono::{DateTime, Utc};
+use plotters::prelude::*;
+use plotters::style::register_font;
+use std::sync::LazyLock;
+
+pub const WIDTH: u32 = 800;
+pub const HEIGHT: u32 = 400;
+
+// Candles drawn across the chart's width
+const CANDLES: usize = 40;
+
+// Size of the preview shown while the full image loads
+pub const THUMBNAIL_WIDTH: u32 = WIDTH / 8;
+pub const THUMBNAIL_HEIGHT: u32 = HEIGHT / 8;
+
+// Bundled so axis labels render the same on any host
+static FONT: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+static FONT_LOADED: LazyLock<Result<(), String>> = LazyLock::new(|| {
+    register_font("sans-serif", FontStyle::Normal, FONT).map_err(|_| "Failed to load the chart font".to_string())
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartStyle {
+    Line,
+    Candles,
+}
+
+pub struct ChartImage {
+    pub png: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+}
+
+// Render price samples as a PNG with a time axis and a USD price scale, plus a small preview
+pub fn render(title: &str, samples: &[(i64, f64)], style: ChartStyle) -> Result<ChartImage, String> {
+    if samples.len() < 2 {
+        return Err("Not enough price history to chart yet".into());
+    }
+    FONT_LOADED.clone()?;
+
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+        let start = samples[0].0;
+        let end = samples[samples.len() - 1].0;
+        let low = samples.iter().map(|(_, p)| *p).fold(f64::INFINITY, f64::min);
+        let high = samples.iter().map(|(_, p)| *p).fold(f64::NEG_INFINITY, f64::max);
+        let padding = ((high - low) * 0.05).max(high * 0.001);
+        // Clock times within two days, dates beyond
+        let time_format = if end - start <= 2 * 24 * 60 * 60 { "%H:%M" } else { "%b %d" };
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(80)
+            .build_cartesian_2d(start..end, (low - padding)..(high + padding))
+            .map_err(|e| e.to_string())?;
+
+        chart
+            .configure_mesh()
+            .light_line_style(RGBColor(240, 240, 240))
+            .x_labels(6)
+            .y_labels(6)
+            .x_label_formatter(&|t| {
+                DateTime::<Utc>::from_timestamp(*t, 0)
+                    .map(|d| d.format(time_format).to_string())
+                    .unwrap_or_default()
+            })
+            .y_label_formatter(&|p| format_price(*p))
+            .x_desc("UTC")
+            .y_desc("USD")
+            .label_style(("sans-serif", 13))
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        match style {
+            ChartStyle::Line => {
+                chart
+                    .draw_series(LineSeries::new(samples.iter().copied(), BLUE.stroke_width(2)))
+                    .map_err(|e| e.to_string())?;
+            }
+            ChartStyle::Candles => {
+                let width = (WIDTH as usize / CANDLES / 2).max(1) as u32;
+                chart
+                    .draw_series(candles(samples).iter().map(|c| {
+                        CandleStick::new(c.start, c.open, c.high.1, c.low.1, c.close, GREEN.filled(), RED.filled(), width)
+                    }))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    Ok(ChartImage {
+        png: encode_png(&pixels, WIDTH, HEIGHT)?,
+        thumbnail: encode_png(&shrink(&pixels, WIDTH, HEIGHT, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT), THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)?,
+    })
+}
+
+// Enough significant digits for sub-cent tokens without cluttering BTC's scale
+fn format_price(price: f64) -> String {
+    match price.abs() {
+        p if p >= 1000.0 => format!("${:.0}", price),
+        p if p >= 1.0 => format!("${:.2}", price),
+        p if p >= 0.01 => format!("${:.4}", price),
+        _ => format!("${:.6}", price),
+    }
+}
+
+fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+// Scale an RGB image down by averaging each block of pixels
+fn shrink(pixels: &[u8], width: u32, height: u32, to_width: u32, to_height: u32) -> Vec<u8> {
+    let (width, height, to_width, to_height) = (width as usize, height as usize, to_width as usize, to_height as usize);
+    let mut out = Vec::with_capacity(to_width * to_height * 3);
+    for y in 0..to_height {
+        let rows = y * height / to_height..((y + 1) * height / to_height).max(y * height / to_height + 1);
+        for x in 0..to_width {
+            let columns = x * width / to_width..((x + 1) * width / to_width).max(x * width / to_width + 1);
+            let mut sum = [0usize; 3];
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += pixels[(row * width + column) * 3 + channel] as usize;
+                    }
+                }
+            }
+            let count = rows.len() * columns.len();
+            out.extend(sum.iter().map(|total| (total / count) as u8));
+        }
+    }
+    out
+}
+
+// Split the samples into equal time buckets, one candle each
+fn candles(samples: &[(i64, f64)]) -> Vec<Ohlc> {
+    let start = samples[0].0;
+    let span = (samples[samples.len() - 1].0 - start).max(1);
+    let mut buckets: Vec<Vec<(i64, f64)>> = vec![Vec::new(); CANDLES];
+    for sample in samples {
+        let index = ((sample.0 - start) as i128 * CANDLES as i128 / (span as i128 + 1)) as usize;
+        buckets[index.min(CANDLES - 1)].push(*sample);
+    }
+    buckets.iter().filter_map(|b| Ohlc::from_samples(b)).collect()
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Unicode sparkline of the samples averaged into `width` equal buckets
+pub fn sparkline(samples: &[(i64, f64)], width: usize) -> Option<String> {
+    if samples.len() < 2 || width == 0 {
+        return None;
+    }
+    let width = width.min(samples.len());
+    let points: Vec<f64> = (0..width)
+        .map(|i| {
+            let bucket = &samples[i * samples.len() / width..(i + 1) * samples.len() / width];
+            bucket.iter().map(|(_, p)| p).sum::<f64>() / bucket.len() as f64
+        })
+        .collect();
+
+    let low = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = high - low;
+    Some(
+        points
+            .iter()
+            .map(|p| {
+                if range <= 0.0 {
+                    SPARKS[SPARKS.len() / 2]
+                } else {
+                    SPARKS[(((p - low) / range) * (SPARKS.len() - 1) as f64).round() as usize]
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_full_image_and_preview() {
+        let samples: Vec<(i64, f64)> = (0..100).map(|i| (i * 600, 10.0 + (i % 7) as f64)).collect();
+        for style in [ChartStyle::Line, ChartStyle::Candles] {
+            let image = render("ICP 24h", &samples, style).unwrap();
+            assert!(image.png.starts_with(b"\x89PNG"));
+            assert!(image.thumbnail.len() < image.png.len());
+        }
+    }
+
+    #[test]
+    fn one_sample_is_not_enough() {
+        assert!(render("ICP 24h", &[(0, 1.0)], ChartStyle::Line).is_err());
+    }
+
+    #[test]
+    fn shrink_averages_blocks() {
+        // 2x1 image of black and white shrunk to one grey pixel
+        assert_eq!(shrink(&[0, 0, 0, 255, 255, 255], 2, 1, 1, 1), vec![127, 127, 127]);
+    }
+
+    #[test]
+    fn prices_keep_significant_digits() {
+        assert_eq!(format_price(64250.4), "$64250");
+        assert_eq!(format_price(9.876), "$9.88");
+        assert_eq!(format_price(0.0123), "$0.0123");
+        assert_eq!(format_price(0.000012), "$0.000012");
+    }
+
+    #[test]
+    fn sparkline_spans_low_to_high() {
+        let samples: Vec<(i64, f64)> = (0..8).map(|i| (i, i as f64)).collect();
+        assert_eq!(sparkline(&samples, 8).unwrap(), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&samples[..1], 8), None);
+    }
+}
@@ -5,30 +5,24 @@ use oc_bots_sdk::types::BotCommandContext;
 use oc_bots_sdk_offchain::AgentRuntime;
 use oc_bots_sdk::oc_api::client::Client;
 use std::sync::LazyLock;
-use chrono::{DateTime, Utc};
-use std::collections::HashMap;
-use std::sync::Mutex;
 use std::sync::Arc;
+use std::time::Duration;
+use crate::commands::alert_monitor::{format_window, AlertKind, AlertMonitor, Indicator};
+use crate::notifier::command_chat;
+use crate::price_source;
+use crate::tokens;
+use crate::util::{parse_amount, parse_period};
 
 static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Alert::definition);
 
 #[derive(Clone)]
 pub struct Alert {
-    alerts: Arc<Mutex<HashMap<String, Vec<AlertData>>>>,
-}
-
-#[derive(Debug, Clone)]
-struct AlertData {
-    price: f64,
-    condition: String,
-    timestamp: DateTime<Utc>,
+    monitor: Arc<AlertMonitor>,
 }
 
 impl Alert {
-    pub fn new() -> Self {
-        Self {
-            alerts: Arc::new(Mutex::new(HashMap::new())),
-        }
+    pub fn new(monitor: Arc<AlertMonitor>) -> Self {
+        Self { monitor }
     }
 
     fn definition() -> BotCommandDefinition {
@@ -89,9 +83,9 @@ impl Alert {
                 },
                 BotCommandParam {
                     name: "price".to_string(),
-                    description: Some("Alert price in USD".to_string()),
+                    description: Some("Alert price in USD, % change for liquidity alerts or level for RSI alerts".to_string()),
                     placeholder: Some("Enter price (e.g. 50000)".to_string()),
-                    required: true,
+                    required: false,
                     param_type: BotCommandParamType::StringParam(StringParam {
                         min_length: 1,
                         max_length: 20,
@@ -101,7 +95,7 @@ impl Alert {
                 },
                 BotCommandParam {
                     name: "condition".to_string(),
-                    description: Some("Alert condition".to_string()),
+                    description: Some("Alert condition, above for a golden cross and below for a death cross".to_string()),
                     placeholder: Some("Select a condition".to_string()),
                     required: true,
                     param_type: BotCommandParamType::StringParam(StringParam {
@@ -120,6 +114,83 @@ impl Alert {
                         multi_line: false,
                     }),
                 },
+                BotCommandParam {
+                    name: "kind".to_string(),
+                    description: Some("What the alert watches".to_string()),
+                    placeholder: Some("Select an alert kind".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Price".to_string(),
+                                value: "price".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Pool Liquidity (TVL)".to_string(),
+                                value: "liquidity".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "RSI".to_string(),
+                                value: "rsi".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "SMA Cross".to_string(),
+                                value: "cross".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "quote".to_string(),
+                    description: Some("Quote token of the pool for liquidity alerts".to_string()),
+                    placeholder: Some("e.g. ckUSDC".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "window".to_string(),
+                    description: Some("Time window for liquidity alerts, candle interval for indicator alerts".to_string()),
+                    placeholder: Some("e.g. 1h".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 2,
+                        max_length: 5,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "periods".to_string(),
+                    description: Some("RSI period, or fast/slow SMA periods for cross alerts".to_string()),
+                    placeholder: Some("e.g. 14 or 50/200".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 7,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "id".to_string(),
+                    description: Some("Id of the alert to remove, as shown by list".to_string()),
+                    placeholder: Some("e.g. 3".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
             ],
             permissions: BotPermissions::from_message_permission(MessagePermission::Text),
             default_role: None,
@@ -143,31 +214,116 @@ impl CommandHandler<AgentRuntime> for Alert {
         match action.as_str() {
             "set" => {
                 let symbol = client.context().command.arg::<String>("symbol").to_uppercase();
-                let price_str = client.context().command.arg::<String>("price");
-                let price = price_str.parse::<f64>().map_err(|_| "Invalid price format")?;
+                let price = client
+                    .context()
+                    .command
+                    .maybe_arg::<String>("price")
+                    // Also the liquidity %, where a negative or NaN value would fire on anything or never
+                    .map(|p| parse_amount(&p).ok_or("Invalid value, use a positive number such as 12.5"))
+                    .transpose()?;
+                let window = client.context().command.maybe_arg::<String>("window");
+                let periods = client.context().command.maybe_arg::<String>("periods");
                 let condition = client.context().command.arg::<String>("condition").to_lowercase();
+                let kind = client.context().command.maybe_arg::<String>("kind").unwrap_or("price".to_string()).to_lowercase();
                 
-                let alert_data = AlertData {
-                    price,
-                    condition: condition.clone(),
-                    timestamp: Utc::now(),
+                let (kind, price, message) = match kind.as_str() {
+                    "price" => {
+                        let price = price.ok_or("Enter the alert price")?;
+                        let message = format!(
+                            "🔔 Price alert set for {} when price {} ${:.2}",
+                            symbol,
+                            match condition.as_str() {
+                                "above" => "rises above",
+                                "below" => "falls below",
+                                _ => return Err("Invalid condition".into()),
+                            },
+                            price
+                        );
+                        (AlertKind::Price, price, message)
+                    },
+                    "liquidity" => {
+                        let price = price.ok_or("Enter the % change to alert on")?;
+                        let base = tokens::find(&symbol).ok_or("Unsupported pool token")?;
+                        let quote = client.context().command.maybe_arg::<String>("quote").unwrap_or("ckUSDC".to_string());
+                        let quote = tokens::find(&quote).ok_or("Unsupported pool token")?;
+                        let window = match window {
+                            Some(w) => parse_period(&w).ok_or("Invalid window, use e.g. 30m, 1h or 1d")?,
+                            None => Duration::from_secs(60 * 60),
+                        };
+                        if window > Duration::from_secs(24 * 60 * 60) {
+                            return Err("Liquidity alert windows can be at most 1d".into());
+                        }
+                        let message = format!(
+                            "💧 Liquidity alert set for the {}/{} pool when TVL {} {:.1}% within {}",
+                            base.symbol,
+                            quote.symbol,
+                            match condition.as_str() {
+                                "above" => "rises",
+                                "below" => "drops",
+                                _ => return Err("Invalid condition".into()),
+                            },
+                            price,
+                            format_window(window)
+                        );
+                        let kind = AlertKind::Liquidity {
+                            quote: quote.symbol.to_string(),
+                            window,
+                        };
+                        (kind, price, message)
+                    },
+                    "rsi" => {
+                        price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+                        let level = price.ok_or("Enter the RSI level, e.g. 30")?;
+                        if !(0.0..=100.0).contains(&level) {
+                            return Err("RSI levels are between 0 and 100".into());
+                        }
+                        let period = match periods {
+                            Some(p) => p.trim().parse::<usize>().ok().filter(|p| (2..=100).contains(p)).ok_or("Invalid RSI period, use e.g. 14")?,
+                            None => 14,
+                        };
+                        let interval = indicator_interval(window, Duration::from_secs(60 * 60), period * 5 + 1)?;
+                        let message = format!(
+                            "📈 Indicator alert set for {} when RSI({}) on {} {} {:.0}",
+                            symbol,
+                            period,
+                            format_window(interval),
+                            match condition.as_str() {
+                                "above" => "rises above",
+                                "below" => "falls below",
+                                _ => return Err("Invalid condition".into()),
+                            },
+                            level
+                        );
+                        (AlertKind::Indicator(Indicator::Rsi { period, interval }), level, message)
+                    },
+                    "cross" => {
+                        price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+                        let (fast, slow) = match periods {
+                            Some(p) => parse_cross_periods(&p).ok_or("Invalid SMA periods, use fast/slow e.g. 50/200")?,
+                            None => (50, 200),
+                        };
+                        let interval = indicator_interval(window, Duration::from_secs(24 * 60 * 60), slow + 2)?;
+                        let message = format!(
+                            "📈 Indicator alert set for a {} {}/{} SMA {} cross on {}",
+                            symbol,
+                            fast,
+                            slow,
+                            match condition.as_str() {
+                                "above" => "golden",
+                                "below" => "death",
+                                _ => return Err("Invalid condition".into()),
+                            },
+                            format_window(interval)
+                        );
+                        (AlertKind::Indicator(Indicator::SmaCross { fast, slow, interval }), 0.0, message)
+                    },
+                    _ => return Err("Invalid alert kind".into()),
                 };
                 
-                let mut alerts = self.alerts.lock().unwrap();
-                alerts.entry(symbol.clone())
-                    .or_insert_with(Vec::new)
-                    .push(alert_data);
-                
-                let message = format!(
-                    "🔔 Price alert set for {} when price {} ${:.2}",
-                    symbol,
-                    match condition.as_str() {
-                        "above" => "rises above",
-                        "below" => "falls below",
-                        _ => return Err("Invalid condition".into()),
-                    },
-                    price
-                );
+                let id = self.monitor
+                    .add_alert(symbol, kind, price, condition, command_chat(client.context()))
+                    .await;
+                let message = format!("{} (#{})", message, id);
                 
                 let response = client
                     .send_text_message(message)
@@ -176,27 +332,8 @@ impl CommandHandler<AgentRuntime> for Alert {
             },
             "list" => {
                 let mut message = "📋 Your Price Alerts:\n\n".to_string();
-                let alerts = self.alerts.lock().unwrap();
-                
-                if alerts.is_empty() {
-                    message.push_str("No alerts set");
-                } else {
-                    for (symbol, alert_list) in alerts.iter() {
-                        for alert in alert_list {
-                            message.push_str(&format!(
-                                "• {} {} ${:.2} (set {})\n",
-                                symbol,
-                                match alert.condition.as_str() {
-                                    "above" => "above",
-                                    "below" => "below",
-                                    _ => "unknown",
-                                },
-                                alert.price,
-                                alert.timestamp.format("%Y-%m-%d %H:%M")
-                            ));
-                        }
-                    }
-                }
+                let chat = command_chat(client.context());
+                message.push_str(&self.monitor.get_alerts(chat.as_ref()).await.join("\n"));
                 
                 let response = client
                     .send_text_message(message)
@@ -204,10 +341,18 @@ impl CommandHandler<AgentRuntime> for Alert {
                 Ok(SuccessResult { message: response })
             },
             "remove" => {
-                let symbol = client.context().command.arg::<String>("symbol").to_uppercase();
-                let mut alerts = self.alerts.lock().unwrap();
-                alerts.remove(&symbol);
-                let message = format!("✅ Removed price alert for {}", symbol);
+                let id = client
+                    .context()
+                    .command
+                    .maybe_arg::<String>("id")
+                    .ok_or("Enter the id of the alert to remove, see /alert list")?;
+                let id = id.trim().trim_start_matches('#').parse::<u64>().map_err(|_| "Invalid alert id")?;
+                let chat = command_chat(client.context());
+                let (_, description) = self.monitor
+                    .remove_alert(chat.as_ref(), id)
+                    .await
+                    .ok_or("No alert with that id in this chat")?;
+                let message = format!("✅ Removed alert #{}: {}", id, description);
                 let response = client
                     .send_text_message(message)
                     .execute_then_return_message(|_, _| ());
@@ -216,4 +361,36 @@ impl CommandHandler<AgentRuntime> for Alert {
             _ => Err("Invalid action".into()),
         }
     }
-} 
\ No newline at end of file
+}
+
+// Candle interval for an indicator alert. The candles it needs have to fit in the history
+// CoinGecko serves at that granularity.
+fn indicator_interval(window: Option<String>, default: Duration, candles: usize) -> Result<Duration, String> {
+    let interval = match window {
+        Some(w) => parse_period(&w).ok_or("Invalid interval, use e.g. 15m, 1h, 4h or 1d")?,
+        None => default,
+    };
+    if interval < Duration::from_secs(15 * 60) {
+        return Err("Indicator intervals must be at least 15m".into());
+    }
+    let covered = interval * candles as u32;
+    if interval < Duration::from_secs(60 * 60) && covered > Duration::from_secs(24 * 60 * 60) {
+        return Err("Intervals under 1h can cover at most a day of candles, use a longer interval or fewer periods".into());
+    }
+    let days = covered.as_secs() / (24 * 60 * 60);
+    if interval < Duration::from_secs(24 * 60 * 60) && days > 90 {
+        return Err("That needs more than 90 days of intraday candles, use a longer interval or fewer periods".into());
+    }
+    if days > 365 {
+        return Err("That needs more than a year of candles, use a shorter interval or fewer periods".into());
+    }
+    Ok(interval)
+}
+
+// Parse "fast/slow" SMA periods such as "50/200"
+fn parse_cross_periods(periods: &str) -> Option<(usize, usize)> {
+    let (fast, slow) = periods.trim().split_once('/')?;
+    let (fast, slow) = (fast.trim().parse::<usize>().ok()?, slow.trim().parse::<usize>().ok()?);
+    (fast >= 2 && fast < slow && slow <= 200).then_some((fast, slow))
+}
+ 
\ No newline at end of file
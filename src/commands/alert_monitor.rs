@@ -1,135 +1,493 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use std::time::Duration;
-use reqwest;
-use serde_json::json;
+use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc};
+use oc_bots_sdk::types::Chat;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use crate::dex_source::DexSource;
+use crate::indicators::{self, Cross};
+use crate::notifier::ChatNotifier;
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use crate::tokens;
 
-// Shared state for alerts
-pub struct AlertMonitor {
-    alerts: Arc<Mutex<Option<HashMap<String, Vec<AlertData>>>>>,
+// Indicators are computed from candles, so there's no point checking them every minute
+const INDICATOR_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Fired alerts are remembered for a little over a week, long enough for weekly digests
+const FIRED_RETENTION_DAYS: i64 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FiredAlert {
+    at: DateTime<Utc>,
+    // Chat the alert was set in
+    chat: Option<Chat>,
+    message: String,
+}
+
+// Alerts that fired within the retention period, appended to a JSON lines file so digests
+// still include them after a restart
+struct FiredLog {
+    file: File,
+    entries: VecDeque<FiredAlert>,
+}
+
+impl FiredLog {
+    // Loads the fired alerts and rewrites the file without the expired ones
+    fn open(data_dir: &str) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir, e))?;
+        let path = PathBuf::from(data_dir).join("fired_alerts.jsonl");
+        let cutoff = Utc::now() - chrono::Duration::days(FIRED_RETENTION_DAYS);
+
+        let mut entries = VecDeque::new();
+        if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+            let mut kept = Vec::new();
+            for line in content.lines() {
+                let Ok(entry) = serde_json::from_str::<FiredAlert>(line) else {
+                    warn!("Skipping unreadable fired alert: {}", line);
+                    continue;
+                };
+                if entry.at >= cutoff {
+                    entries.push_back(entry);
+                    kept.push(line);
+                }
+            }
+            let mut compacted = kept.join("\n");
+            if !compacted.is_empty() {
+                compacted.push('\n');
+            }
+            fs::write(&path, compacted).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+        Ok(Self { file, entries })
+    }
+
+    fn push(&mut self, entry: FiredAlert) {
+        match serde_json::to_string(&entry) {
+            Ok(line) => {
+                if let Err(e) = writeln!(self.file, "{}", line) {
+                    error!("Failed to record fired alert: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize fired alert: {}", e),
+        }
+        let cutoff = entry.at - chrono::Duration::days(FIRED_RETENTION_DAYS);
+        self.entries.push_back(entry);
+        while self.entries.front().is_some_and(|e| e.at < cutoff) {
+            self.entries.pop_front();
+        }
+    }
+
+    // Messages of the alerts that fired in a chat since `since`, oldest first
+    fn since(&self, chat: &Chat, since: DateTime<Utc>) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.at >= since && e.chat.as_ref() == Some(chat))
+            .map(|e| e.message.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AlertKind {
+    Price,
+    // Percent change of a pool's TVL within a time window
+    Liquidity { quote: String, window: Duration },
+    // Technical indicator computed from price candles
+    Indicator(Indicator),
+}
+
+#[derive(Debug, Clone)]
+pub enum Indicator {
+    // RSI over `period` candles of `interval`
+    Rsi { period: usize, interval: Duration },
+    // Fast SMA crossing the slow one, golden when the condition is above and death when below
+    SmaCross { fast: usize, slow: usize, interval: Duration },
 }
 
 #[derive(Debug, Clone)]
 pub struct AlertData {
+    pub id: u64,
+    pub kind: AlertKind,
+    // USD price for price alerts, percent change for liquidity alerts, level for RSI alerts
     pub price: f64,
     pub condition: String,
+    pub chat: Option<Chat>,
     pub timestamp: DateTime<Utc>,
 }
 
-impl AlertMonitor {
-    pub fn new() -> Self {
-        Self {
-            alerts: Arc::new(Mutex::new(Some(HashMap::new()))),
+impl AlertData {
+    pub fn describe(&self, symbol: &str) -> String {
+        let direction = match (&self.kind, self.condition.as_str()) {
+            (AlertKind::Price, "above") => "above",
+            (AlertKind::Price, "below") => "below",
+            (AlertKind::Liquidity { .. }, "above") => "TVL rises",
+            (AlertKind::Liquidity { .. }, "below") => "TVL drops",
+            (AlertKind::Indicator(Indicator::Rsi { .. }), "above") => "above",
+            (AlertKind::Indicator(Indicator::Rsi { .. }), "below") => "below",
+            (AlertKind::Indicator(Indicator::SmaCross { .. }), "above") => "golden",
+            (AlertKind::Indicator(Indicator::SmaCross { .. }), "below") => "death",
+            _ => "unknown",
+        };
+        match &self.kind {
+            AlertKind::Price => format!(
+                "{} {} ${:.2}",
+                symbol,
+                direction,
+                self.price
+            ),
+            AlertKind::Liquidity { quote, window } => format!(
+                "{}/{} pool {} {:.1}% within {}",
+                symbol,
+                quote,
+                direction,
+                self.price,
+                format_window(*window)
+            ),
+            AlertKind::Indicator(Indicator::Rsi { period, interval }) => format!(
+                "{} RSI({}) on {} {} {:.0}",
+                symbol,
+                period,
+                format_window(*interval),
+                direction,
+                self.price
+            ),
+            AlertKind::Indicator(Indicator::SmaCross { fast, slow, interval }) => format!(
+                "{} {}/{} SMA {} cross on {}",
+                symbol,
+                fast,
+                slow,
+                direction,
+                format_window(*interval)
+            ),
         }
     }
+}
+
+// Shared state for alerts, checked by a background task
+pub struct AlertMonitor {
+    alerts: Arc<Mutex<HashMap<String, Vec<AlertData>>>>,
+    fired: Arc<Mutex<FiredLog>>,
+    next_id: AtomicU64,
+    dex_source: Arc<DexSource>,
+    notifier: Arc<ChatNotifier>,
+    price_history: Arc<PriceHistory>,
+}
+
+impl AlertMonitor {
+    pub fn new(
+        dex_source: Arc<DexSource>,
+        notifier: Arc<ChatNotifier>,
+        price_history: Arc<PriceHistory>,
+        data_dir: &str,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            alerts: Arc::new(Mutex::new(HashMap::new())),
+            fired: Arc::new(Mutex::new(FiredLog::open(data_dir)?)),
+            next_id: AtomicU64::new(1),
+            dex_source,
+            notifier,
+            price_history,
+        })
+    }
 
-    pub async fn add_alert(&self, symbol: String, price: f64, condition: String) {
+    pub async fn add_alert(&self, symbol: String, kind: AlertKind, price: f64, condition: String, chat: Option<Chat>) -> u64 {
         let mut alerts = self.alerts.lock().await;
-        if let Some(alerts_map) = alerts.as_mut() {
-            let alert_data = AlertData {
-                price,
-                condition,
-                timestamp: Utc::now(),
-            };
-            alerts_map.entry(symbol)
-                .or_insert_with(Vec::new)
-                .push(alert_data);
-        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let alert_data = AlertData {
+            id,
+            kind,
+            price,
+            condition,
+            chat,
+            timestamp: Utc::now(),
+        };
+        alerts.entry(symbol)
+            .or_insert_with(Vec::new)
+            .push(alert_data);
+        id
     }
 
-    pub async fn remove_alert(&self, symbol: &str) {
+    // Remove an alert set in the chat, returning its symbol and description
+    pub async fn remove_alert(&self, chat: Option<&Chat>, id: u64) -> Option<(String, String)> {
         let mut alerts = self.alerts.lock().await;
-        if let Some(alerts_map) = alerts.as_mut() {
-            alerts_map.remove(symbol);
+        let mut removed = None;
+        for (symbol, alert_list) in alerts.iter_mut() {
+            if let Some(index) = alert_list.iter().position(|a| a.id == id && a.chat.as_ref() == chat) {
+                let alert = alert_list.remove(index);
+                removed = Some((symbol.clone(), alert.describe(symbol)));
+                break;
+            }
         }
+        alerts.retain(|_, alert_list| !alert_list.is_empty());
+        removed
     }
 
-    pub async fn get_alerts(&self) -> Vec<String> {
+    // Alerts set in the chat
+    pub async fn get_alerts(&self, chat: Option<&Chat>) -> Vec<String> {
         let alerts = self.alerts.lock().await;
-        if let Some(alerts_map) = alerts.as_ref() {
-            let mut result = Vec::new();
-            for (symbol, alert_list) in alerts_map {
-                for alert in alert_list {
-                    result.push(format!(
-                        "• {} {} ${:.2} (set {})",
-                        symbol,
-                        match alert.condition.as_str() {
-                            "above" => "above",
-                            "below" => "below",
-                            _ => "unknown",
-                        },
-                        alert.price,
-                        alert.timestamp.format("%Y-%m-%d %H:%M")
-                    ));
-                }
-            }
-            if result.is_empty() {
-                result.push("No alerts set".to_string());
+        let mut result = Vec::new();
+        for (symbol, alert_list) in alerts.iter() {
+            for alert in alert_list.iter().filter(|a| a.chat.as_ref() == chat) {
+                result.push(format!(
+                    "• #{} {} (set {})",
+                    alert.id,
+                    alert.describe(symbol),
+                    alert.timestamp.format("%Y-%m-%d %H:%M")
+                ));
             }
-            result
-        } else {
-            vec!["No alerts set".to_string()]
         }
+        if result.is_empty() {
+            result.push("No alerts set".to_string());
+        }
+        result
+    }
+
+    // Messages of the alerts that fired in a chat since `since`, oldest first
+    pub async fn fired_since(&self, chat: &Chat, since: DateTime<Utc>) -> Vec<String> {
+        self.fired.lock().await.since(chat, since)
     }
 
     pub async fn start_monitoring(&self) {
         let alerts = self.alerts.clone();
+        let fired = self.fired.clone();
+        let dex_source = self.dex_source.clone();
+        let notifier = self.notifier.clone();
+        let price_history = self.price_history.clone();
         tokio::spawn(async move {
+            // TVL samples per pool, kept for as long as the longest liquidity alert window
+            let mut tvl_history: HashMap<String, VecDeque<(Instant, f64)>> = HashMap::new();
+            let mut indicators_checked: Option<Instant> = None;
+
             loop {
                 // Check prices every minute
                 tokio::time::sleep(Duration::from_secs(60)).await;
-                
-                let alerts_guard = alerts.lock().await;
-                if let Some(alerts_map) = alerts_guard.as_ref() {
-                    for (symbol, alert_list) in alerts_map {
-                        // Get current price
-                        if let Ok(current_price) = get_current_price(symbol).await {
-                            for alert in alert_list {
-                                let should_trigger = match alert.condition.as_str() {
-                                    "above" => current_price > alert.price,
-                                    "below" => current_price < alert.price,
-                                    _ => false,
-                                };
-                                
-                                if should_trigger {
-                                    // TODO: Send notification to user
-                                    println!("Alert triggered for {}: price {} ${:.2}",
-                                        symbol,
-                                        alert.condition,
-                                        alert.price
-                                    );
+
+                // Evaluate against a snapshot so the lock isn't held across network calls
+                let snapshot = alerts.lock().await.clone();
+                let mut triggered = Vec::new();
+                let check_indicators = indicators_checked.is_none_or(|at| at.elapsed() >= INDICATOR_CHECK_INTERVAL);
+                if check_indicators {
+                    indicators_checked = Some(Instant::now());
+                }
+
+                for (symbol, alert_list) in &snapshot {
+                    if alert_list.iter().any(|a| matches!(a.kind, AlertKind::Price)) {
+                        match price_source::usd_price(symbol).await {
+                            Ok(current_price) => {
+                                for alert in alert_list.iter().filter(|a| matches!(a.kind, AlertKind::Price)) {
+                                    let should_trigger = match alert.condition.as_str() {
+                                        "above" => current_price > alert.price,
+                                        "below" => current_price < alert.price,
+                                        _ => false,
+                                    };
+                                    if should_trigger {
+                                        triggered.push((alert.clone(), format!(
+                                            "🔔 Price alert: {} is now ${:.2} ({})",
+                                            symbol,
+                                            current_price,
+                                            alert.describe(symbol)
+                                        )));
+                                    }
                                 }
                             }
+                            Err(e) => error!("Failed to fetch {} price: {}", symbol, e),
+                        }
+                    }
+
+                    for alert in alert_list {
+                        let AlertKind::Liquidity { quote, window } = &alert.kind else {
+                            continue;
+                        };
+                        let pool = format!("{}/{}", symbol, quote);
+                        let Some(change) = tvl_change(&dex_source, &mut tvl_history, symbol, quote, *window).await else {
+                            continue;
+                        };
+                        let should_trigger = match alert.condition.as_str() {
+                            "above" => change >= alert.price,
+                            "below" => change <= -alert.price,
+                            _ => false,
+                        };
+                        if should_trigger {
+                            triggered.push((alert.clone(), format!(
+                                "💧 Liquidity alert: {} pool TVL changed {:+.1}% in {} ({})",
+                                pool,
+                                change,
+                                format_window(*window),
+                                alert.describe(symbol)
+                            )));
+                        }
+                    }
+
+                    for alert in alert_list.iter().filter(|_| check_indicators) {
+                        let AlertKind::Indicator(indicator) = &alert.kind else {
+                            continue;
+                        };
+                        match indicator_triggered(&price_history, symbol, indicator, alert).await {
+                            Ok(Some(reading)) => triggered.push((alert.clone(), format!(
+                                "📈 Indicator alert: {} ({})",
+                                reading,
+                                alert.describe(symbol)
+                            ))),
+                            Ok(None) => {}
+                            Err(e) => error!("Failed to evaluate {} indicator alert: {}", symbol, e),
+                        }
+                    }
+                }
+
+                if triggered.is_empty() {
+                    continue;
+                }
+
+                // Alerts fire once, then they're removed
+                let mut alerts_guard = alerts.lock().await;
+                let mut fired_guard = fired.lock().await;
+                let now = Utc::now();
+                for (alert, message) in triggered {
+                    fired_guard.push(FiredAlert { at: now, chat: alert.chat, message: message.clone() });
+                    match &alert.chat {
+                        Some(chat) => {
+                            notifier.send_text(chat, message);
                         }
+                        None => warn!("Alert #{} has no chat to post into: {}", alert.id, message),
+                    }
+                    for alert_list in alerts_guard.values_mut() {
+                        alert_list.retain(|a| a.id != alert.id);
                     }
                 }
+                alerts_guard.retain(|_, alert_list| !alert_list.is_empty());
             }
         });
     }
 }
 
-async fn get_current_price(symbol: &str) -> Result<f64, String> {
-    let url = format!("https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd", 
-        match symbol {
-            "BTC" => "bitcoin",
-            "ETH" => "ethereum",
-            "ICP" => "internet-computer",
-            _ => return Err("Unsupported symbol".into()),
+// Record the current TVL of a pair across DEXes and return its percent change over the window.
+// Several alerts on the same pool share one sample per poll.
+async fn tvl_change(
+    dex_source: &DexSource,
+    tvl_history: &mut HashMap<String, VecDeque<(Instant, f64)>>,
+    symbol: &str,
+    quote: &str,
+    window: Duration,
+) -> Option<f64> {
+    let pool = format!("{}/{}", symbol, quote);
+    let now = Instant::now();
+
+    let fresh = tvl_history
+        .get(&pool)
+        .and_then(|samples| samples.back())
+        .is_some_and(|(at, _)| now.duration_since(*at) < Duration::from_secs(30));
+    if !fresh {
+        let (base, quote) = (tokens::find(symbol)?, tokens::find(quote)?);
+        let pools = dex_source.pool_liquidity(base, quote).await;
+        if pools.is_empty() {
+            return None;
         }
-    );
-    
-    let response = reqwest::get(&url)
-        .await
-        .map_err(|e| e.to_string())?;
-        
-    let data: serde_json::Value = response.json()
-        .await
-        .map_err(|e| e.to_string())?;
-        
-    let price = data[symbol.to_lowercase()]["usd"]
-        .as_f64()
-        .ok_or_else(|| "Failed to parse price".to_string())?;
-        
-    Ok(price)
-} 
\ No newline at end of file
+        let tvl: f64 = pools.iter().map(|p| p.tvl).sum();
+        tvl_history.entry(pool.clone()).or_default().push_back((now, tvl));
+    }
+
+    let samples = tvl_history.get_mut(&pool)?;
+    // Keep a day of samples, enough for any reasonable window
+    while samples.front().is_some_and(|(at, _)| now.duration_since(*at) > Duration::from_secs(24 * 60 * 60)) {
+        samples.pop_front();
+    }
+
+    let (_, current) = *samples.back()?;
+    let (_, start) = *samples.iter().find(|(at, _)| now.duration_since(*at) <= window)?;
+    if start <= 0.0 {
+        return None;
+    }
+    Some((current - start) / start * 100.0)
+}
+
+// Evaluate an indicator alert against the latest candles, returning the reading when it fires.
+// Too little history to compute the indicator means it doesn't fire yet.
+async fn indicator_triggered(
+    price_history: &PriceHistory,
+    symbol: &str,
+    indicator: &Indicator,
+    alert: &AlertData,
+) -> Result<Option<String>, String> {
+    match indicator {
+        Indicator::Rsi { period, interval } => {
+            // Wilder smoothing needs a few periods of warm-up to settle
+            let closes = candle_closes(price_history, symbol, *interval, period * 5 + 1).await?;
+            let Some(rsi) = indicators::rsi(&closes, *period) else {
+                return Ok(None);
+            };
+            let should_trigger = match alert.condition.as_str() {
+                "above" => rsi > alert.price,
+                "below" => rsi < alert.price,
+                _ => false,
+            };
+            Ok(should_trigger.then(|| format!("{} RSI({}) on {} is {:.1}", symbol, period, format_window(*interval), rsi)))
+        }
+        Indicator::SmaCross { fast, slow, interval } => {
+            let closes = candle_closes(price_history, symbol, *interval, slow + 2).await?;
+            let should_trigger = matches!(
+                (indicators::sma_cross(&closes, *fast, *slow), alert.condition.as_str()),
+                (Some(Cross::Golden), "above") | (Some(Cross::Death), "below")
+            );
+            Ok(should_trigger.then(|| format!("{} {}/{} SMA crossed on {}", symbol, fast, slow, format_window(*interval))))
+        }
+    }
+}
+
+async fn candle_closes(price_history: &PriceHistory, symbol: &str, interval: Duration, count: usize) -> Result<Vec<f64>, String> {
+    let candles = price_history.candles(symbol, interval.as_secs() as i64, count).await?;
+    Ok(candles.iter().map(|c| c.close).collect())
+}
+
+pub fn format_window(window: Duration) -> String {
+    let minutes = window.as_secs() / 60;
+    if minutes % (24 * 60) == 0 {
+        format!("{}d", minutes / (24 * 60))
+    } else if minutes % 60 == 0 {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{}m", minutes)
+    }
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat() -> Chat {
+        serde_json::from_str(r#"{"Group":"rrkah-fqaaa-aaaaa-aaaaq-cai"}"#).unwrap()
+    }
+
+    #[test]
+    fn fired_alerts_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("acubot-fired-{}", std::process::id()));
+        let data_dir = dir.to_str().unwrap();
+        let now = Utc::now();
+        {
+            let mut log = FiredLog::open(data_dir).unwrap();
+            log.push(FiredAlert { at: now - chrono::Duration::days(9), chat: Some(chat()), message: "old".to_string() });
+            log.push(FiredAlert { at: now, chat: Some(chat()), message: "ICP above $10".to_string() });
+            log.push(FiredAlert { at: now, chat: None, message: "no chat".to_string() });
+        }
+
+        let log = FiredLog::open(data_dir).unwrap();
+        assert_eq!(log.since(&chat(), now - chrono::Duration::days(10)), vec!["ICP above $10".to_string()]);
+        assert!(log.since(&chat(), now + chrono::Duration::seconds(1)).is_empty());
+        // Expired entries are dropped from the file
+        let content = fs::read_to_string(dir.join("fired_alerts.jsonl")).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use crate::config::ArbPair;
+use crate::dex_source::{Dex, DexSource};
+use crate::notifier::ChatNotifier;
+use crate::storage::JsonStore;
+use crate::tokens::{self, Token};
+use oc_bots_sdk::types::Chat;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct ArbOpportunity {
+    pub base: String,
+    pub quote: String,
+    pub buy_dex: Dex,
+    pub buy_price: f64,
+    pub sell_dex: Dex,
+    pub sell_price: f64,
+    // Percent gained by buying on one DEX and selling on the other, net of pool and ledger fees
+    pub spread: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArbSubscription {
+    chat: Chat,
+    // Minimum spread the chat wants to hear about
+    min_spread: f64,
+}
+
+impl ArbOpportunity {
+    fn is_same_route(&self, other: &ArbOpportunity) -> bool {
+        self.base == other.base
+            && self.quote == other.quote
+            && self.buy_dex == other.buy_dex
+            && self.sell_dex == other.sell_dex
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "• {}/{}: buy on {} at {:.6}, sell on {} at {:.6} (+{:.2}%)",
+            self.base,
+            self.quote,
+            self.buy_dex.name(),
+            self.buy_price,
+            self.sell_dex.name(),
+            self.sell_price,
+            self.spread
+        )
+    }
+}
+
+// Compares pool prices across DEXes and posts price gaps into subscribed chats
+pub struct ArbMonitor {
+    dex_source: Arc<DexSource>,
+    notifier: Arc<ChatNotifier>,
+    pairs: Vec<ArbPair>,
+    min_spread: f64,
+    subscriptions: JsonStore<Vec<ArbSubscription>>,
+    opportunities: Mutex<Vec<ArbOpportunity>>,
+    // Ledger transfer fees by token symbol, fetched once
+    fees: Mutex<HashMap<&'static str, f64>>,
+}
+
+impl ArbMonitor {
+    pub fn new(
+        dex_source: Arc<DexSource>,
+        notifier: Arc<ChatNotifier>,
+        pairs: Vec<ArbPair>,
+        min_spread: f64,
+        data_dir: &str,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            dex_source,
+            notifier,
+            pairs,
+            min_spread,
+            subscriptions: JsonStore::open(data_dir, "arb_subscriptions.json")?,
+            opportunities: Mutex::new(Vec::new()),
+            fees: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn min_spread(&self) -> f64 {
+        self.min_spread
+    }
+
+    pub fn subscribe(&self, chat: Chat, min_spread: Option<f64>) -> Result<f64, String> {
+        let min_spread = min_spread.unwrap_or(self.min_spread);
+        if !min_spread.is_finite() || min_spread <= 0.0 {
+            return Err("Minimum spread must be a positive %".into());
+        }
+        self.subscriptions.update(|subscriptions| {
+            subscriptions.retain(|s| s.chat != chat);
+            subscriptions.push(ArbSubscription { chat, min_spread });
+        })?;
+        Ok(min_spread)
+    }
+
+    pub fn unsubscribe(&self, chat: &Chat) -> Result<bool, String> {
+        self.subscriptions.update(|subscriptions| {
+            let before = subscriptions.len();
+            subscriptions.retain(|s| s.chat != *chat);
+            subscriptions.len() != before
+        })
+    }
+
+    pub fn subscription(&self, chat: &Chat) -> Option<f64> {
+        self.subscriptions
+            .read(|subscriptions| subscriptions.iter().find(|s| s.chat == *chat).map(|s| s.min_spread))
+    }
+
+    // Opportunities from the latest scan that clear the configured spread
+    pub async fn current_opportunities(&self) -> Vec<ArbOpportunity> {
+        let opportunities = self.opportunities.lock().await;
+        opportunities
+            .iter()
+            .filter(|o| o.spread >= self.min_spread)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn start_monitoring(self: Arc<Self>, poll_interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                self.scan().await;
+                tokio::time::sleep(poll_interval).await;
+            }
+        });
+    }
+
+    async fn scan(&self) {
+        let mut found = Vec::new();
+        for pair in &self.pairs {
+            let (Some(base), Some(quote)) = (tokens::find(&pair.base), tokens::find(&pair.quote)) else {
+                continue;
+            };
+            let (base_fee, quote_fee) = match (self.transfer_fee(base).await, self.transfer_fee(quote).await) {
+                (Ok(base_fee), Ok(quote_fee)) => (base_fee, quote_fee),
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("Skipping {}/{} arbitrage, no ledger fee: {}", base.symbol, quote.symbol, e);
+                    continue;
+                }
+            };
+            let prices = self.dex_source.pool_prices(base, quote, pair.size).await;
+            for buy in &prices {
+                for sell in &prices {
+                    if buy.dex == sell.dex {
+                        continue;
+                    }
+                    let spread = net_spread(buy.buy, sell.sell, pair.size, base_fee, quote_fee);
+                    if spread > 0.0 {
+                        found.push(ArbOpportunity {
+                            base: base.symbol.to_string(),
+                            quote: quote.symbol.to_string(),
+                            buy_dex: buy.dex,
+                            buy_price: buy.buy,
+                            sell_dex: sell.dex,
+                            sell_price: sell.sell,
+                            spread,
+                        });
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| b.spread.total_cmp(&a.spread));
+
+        let previous = std::mem::replace(&mut *self.opportunities.lock().await, found.clone());
+
+        // Only post gaps that weren't already above the chat's threshold last scan
+        let subscriptions = self.subscriptions.read(|subscriptions| subscriptions.clone());
+        for ArbSubscription { chat, min_spread } in subscriptions {
+            let fresh: Vec<String> = found
+                .iter()
+                .filter(|o| o.spread >= min_spread)
+                .filter(|o| !previous.iter().any(|p| p.is_same_route(o) && p.spread >= min_spread))
+                .map(|o| o.describe())
+                .collect();
+            if !fresh.is_empty() {
+                self.notifier.send_text(
+                    &chat,
+                    format!("⚖️ Arbitrage Opportunities\n\n{}", fresh.join("\n")),
+                );
+            }
+        }
+    }
+
+    async fn transfer_fee(&self, token: &Token) -> Result<f64, String> {
+        let mut fees = self.fees.lock().await;
+        if let Some(fee) = fees.get(token.symbol) {
+            return Ok(*fee);
+        }
+        let fee = self.dex_source.transfer_fee(token).await?;
+        fees.insert(token.symbol, fee);
+        Ok(fee)
+    }
+}
+
+// Percent gained by buying `size` base tokens on one DEX and selling them on another, after the
+// ledger fees of both legs: quote tokens sent to the buy DEX and base tokens sent to the sell DEX.
+// Pool fees and price impact are already in the quoted prices.
+fn net_spread(buy: f64, sell: f64, size: f64, base_fee: f64, quote_fee: f64) -> f64 {
+    let cost = buy * size + quote_fee;
+    let proceeds = (size - base_fee) * sell;
+    (proceeds - cost) / cost * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread_without_fees_is_the_price_gap() {
+        assert!((net_spread(10.0, 10.5, 100.0, 0.0, 0.0) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ledger_fees_of_both_legs_come_off_the_spread() {
+        // Buy 100 at 10 for 1000 + 1 fee, sell 99.5 at 10.5 for 1044.75
+        let spread = net_spread(10.0, 10.5, 100.0, 0.5, 1.0);
+        assert!((spread - (1044.75 - 1001.0) / 1001.0 * 100.0).abs() < 1e-9);
+        assert!(spread < net_spread(10.0, 10.5, 100.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn fees_can_wipe_out_a_small_gap() {
+        assert!(net_spread(10.0, 10.01, 1.0, 0.01, 0.01) < 0.0);
+    }
+}
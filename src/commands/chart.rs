@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use base64::prelude::*;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::{BotCommandContext, ImageContent, MessageContentInitial, ThumbnailData};
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::blob_storage::BlobStorage;
+use crate::chart::{self, ChartStyle};
+use crate::notifier::command_chat;
+use crate::price_history::{Ohlc, PriceHistory};
+use crate::price_source;
+use crate::util::parse_period;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Chart::definition);
+
+pub struct Chart {
+    history: Arc<PriceHistory>,
+    blob_storage: Arc<BlobStorage>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Chart {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let symbol = client.context().command.arg::<String>("symbol");
+        let symbol = price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+        let period = client.context().command.maybe_arg::<String>("period").unwrap_or_else(|| "24h".to_string());
+        let duration = parse_period(&period).ok_or("Invalid period, use e.g. 24h, 7d or 4w")?;
+        let style = match client.context().command.maybe_arg::<String>("style").as_deref() {
+            None | Some("line") => ChartStyle::Line,
+            Some("candles") => ChartStyle::Candles,
+            Some(_) => return Err("Style must be line or candles".into()),
+        };
+
+        let samples = self.history.series(symbol, duration.as_secs() as i64).await?;
+        let ohlc = Ohlc::from_samples(&samples).ok_or("No price history for that period yet")?;
+        let image = chart::render(&format!("{} {}", symbol, period), &samples, style)?;
+
+        let caption = format!(
+            "📈 {} {}: ${:.4} → ${:.4} ({}) • High ${:.4} • Low ${:.4}",
+            symbol,
+            period,
+            ohlc.open,
+            ohlc.close,
+            ohlc.change().map_or("n/a".to_string(), |c| format!("{:+.2}%", c)),
+            ohlc.high.1,
+            ohlc.low.1
+        );
+        // The full image is uploaded for the chat, only the small preview travels inline
+        let chat = command_chat(client.context()).ok_or("Charts can only be sent in a chat")?;
+        let blob_reference = self.blob_storage.upload(&image.png, "image/png", vec![chat.canister_id().into()]).await?;
+        let content = MessageContentInitial::Image(ImageContent {
+            width: chart::WIDTH,
+            height: chart::HEIGHT,
+            thumbnail_data: ThumbnailData(format!("data:image/png;base64,{}", BASE64_STANDARD.encode(&image.thumbnail))),
+            caption: Some(caption),
+            mime_type: "image/png".to_string(),
+            blob_reference: Some(blob_reference),
+        });
+
+        let response = client
+            .send_message(content)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Chart {
+    pub fn new(history: Arc<PriceHistory>, blob_storage: Arc<BlobStorage>) -> Self {
+        Self { history, blob_storage }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "chart".to_string(),
+            description: Some("Price chart image for a period".to_string()),
+            placeholder: Some("Drawing chart...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "symbol".to_string(),
+                    description: Some("Symbol to chart".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "period".to_string(),
+                    description: Some("How far back to chart, defaults to 24h".to_string()),
+                    placeholder: Some("e.g. 24h, 7d, 4w".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 2,
+                        max_length: 5,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "style".to_string(),
+                    description: Some("Line or candlestick chart, defaults to line".to_string()),
+                    placeholder: Some("Select a style".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Line".to_string(),
+                                value: "line".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Candles".to_string(),
+                                value: "candles".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Image),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
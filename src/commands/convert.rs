@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::LazyLock;
+use crate::price_source;
+use crate::util::{format_amount, parse_amount};
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Convert::definition);
+
+pub struct Convert;
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Convert {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let amount = client.context().command.arg::<String>("amount");
+        let amount = parse_amount(&amount).ok_or("Invalid amount, use e.g. 250, 0.01 or 1.5k")?;
+        let from = client.context().command.arg::<String>("from");
+        let from = price_source::find_currency(&from).ok_or_else(|| format!("Unsupported currency {}", from))?;
+        let to = client.context().command.arg::<String>("to");
+        let to = price_source::find_currency(&to).ok_or_else(|| format!("Unsupported currency {}", to))?;
+
+        // Amounts can't be more precise than the smallest unit of the currency
+        let from_decimals = price_source::decimals(from).unwrap_or(8);
+        let to_decimals = price_source::decimals(to).unwrap_or(8);
+        let scale = 10f64.powi(from_decimals as i32);
+        if ((amount * scale).round() / scale - amount).abs() > amount * 1e-12 {
+            return Err(format!("{} only has {} decimals", from, from_decimals));
+        }
+
+        let rate = price_source::cross_rate(from, to).await?;
+        let message = format!(
+            "💱 {} {} = {} {}\n1 {} = {} {}",
+            format_amount(amount, from_decimals),
+            from,
+            format_amount(amount * rate, to_decimals),
+            to,
+            from,
+            format_amount(rate, to_decimals.max(4)),
+            to
+        );
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Convert {
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "convert".to_string(),
+            description: Some("Convert an amount between tokens and fiat currencies".to_string()),
+            placeholder: Some("Converting...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Amount to convert, k, m and b suffixes allowed".to_string()),
+                    placeholder: Some("e.g. 250 or 1.5k".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 30,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "from".to_string(),
+                    description: Some("Token or fiat currency to convert from".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "to".to_string(),
+                    description: Some("Token or fiat currency to convert to".to_string()),
+                    placeholder: Some("e.g. EUR".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
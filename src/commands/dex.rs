@@ -4,11 +4,62 @@ use oc_bots_sdk::api::definition::*;
 use oc_bots_sdk::types::BotCommandContext;
 use oc_bots_sdk_offchain::AgentRuntime;
 use oc_bots_sdk::oc_api::client::Client;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::LazyLock;
+use candid::Principal;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::commands::arb_monitor::ArbMonitor;
+use crate::commands::whale_monitor::{DeliveryMode, WhaleMonitor};
+use crate::dex_source::{DexSource, LpPosition};
+use crate::notifier::command_chat;
+use crate::storage::JsonStore;
+use crate::tokens;
+use crate::util::parse_amount;
 
 static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(DexMonitor::definition);
 
-pub struct DexMonitor;
+// Anyone can look up any principal, so baselines are capped and dropped once unused
+const MAX_BASELINES: usize = 1000;
+const BASELINE_EXPIRY_DAYS: i64 = 90;
+
+pub struct DexMonitor {
+    whale_monitor: Arc<WhaleMonitor>,
+    arb_monitor: Arc<ArbMonitor>,
+    dex_source: Arc<DexSource>,
+    // Token amounts of each LP position when it was first looked up, for impermanent loss.
+    // Neither DEX reports what was deposited, so this is the closest starting point.
+    position_baselines: JsonStore<HashMap<String, PositionBaseline>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PositionBaseline {
+    amount_0: f64,
+    amount_1: f64,
+    timestamp: DateTime<Utc>,
+    // When the position was last looked up
+    #[serde(default)]
+    last_seen: Option<DateTime<Utc>>,
+}
+
+impl PositionBaseline {
+    fn last_seen(&self) -> DateTime<Utc> {
+        self.last_seen.unwrap_or(self.timestamp)
+    }
+}
+
+// Drop baselines unused for BASELINE_EXPIRY_DAYS, then the least recently seen past MAX_BASELINES
+fn prune_baselines(baselines: &mut HashMap<String, PositionBaseline>, now: DateTime<Utc>) {
+    baselines.retain(|_, b| now - b.last_seen() <= chrono::Duration::days(BASELINE_EXPIRY_DAYS));
+    if baselines.len() > MAX_BASELINES {
+        let mut seen: Vec<(DateTime<Utc>, String)> = baselines.iter().map(|(k, b)| (b.last_seen(), k.clone())).collect();
+        seen.sort();
+        for (_, key) in seen.iter().take(baselines.len() - MAX_BASELINES) {
+            baselines.remove(key);
+        }
+    }
+}
 
 #[async_trait]
 impl CommandHandler<AgentRuntime> for DexMonitor {
@@ -49,38 +100,430 @@ impl CommandHandler<AgentRuntime> for DexMonitor {
                     .execute_then_return_message(|_, _| ());
                 Ok(SuccessResult { message: response })
             },
+            "subscribe" => {
+                let chat = command_chat(client.context()).ok_or("Subscriptions can only be set up in a chat")?;
+                let feed = client.context().command.maybe_arg::<String>("feed").unwrap_or("whale".to_string()).to_lowercase();
+                let message = match feed.as_str() {
+                    "whale" => {
+                        let symbol = client.context().command.maybe_arg::<String>("token").ok_or("Token is required")?.to_uppercase();
+                        let token = tokens::find(&symbol).ok_or("Unsupported token")?;
+                        let threshold = client.context().command.maybe_arg::<String>("amount").ok_or("Threshold amount is required")?;
+                        let threshold = parse_amount(&threshold).ok_or("Invalid threshold, use a positive amount such as 10000 or 10k")?;
+                        let mode = match client.context().command.maybe_arg::<String>("mode").as_deref() {
+                            None | Some("instant") => DeliveryMode::Instant,
+                            Some("digest") => DeliveryMode::Digest,
+                            _ => return Err("Invalid mode".into()),
+                        };
+
+                        self.whale_monitor.subscribe(chat, token.symbol.to_string(), threshold, mode)?;
+
+                        format!(
+                            "🐋 Subscribed to {} transfers of {:.2} {} or more{}",
+                            token.name,
+                            threshold,
+                            token.symbol,
+                            match mode {
+                                DeliveryMode::Instant => "",
+                                DeliveryMode::Digest => " (digest mode)",
+                            }
+                        )
+                    },
+                    "arb" => {
+                        // The amount is read as the minimum spread in percent
+                        let min_spread = match client.context().command.maybe_arg::<String>("amount") {
+                            Some(s) => Some(parse_amount(&s).ok_or("Invalid spread, use a positive % such as 1.5")?),
+                            None => None,
+                        };
+                        let min_spread = self.arb_monitor.subscribe(chat, min_spread)?;
+                        format!(
+                            "⚖️ Subscribed to arbitrage opportunities with a spread of {:.2}% or more after fees",
+                            min_spread
+                        )
+                    },
+                    _ => return Err("Unsupported feed".into()),
+                };
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
+            "unsubscribe" => {
+                let chat = command_chat(client.context()).ok_or("Subscriptions can only be set up in a chat")?;
+                let feed = client.context().command.maybe_arg::<String>("feed").unwrap_or("whale".to_string()).to_lowercase();
+                let message = if feed == "arb" {
+                    if self.arb_monitor.unsubscribe(&chat)? {
+                        "✅ Removed arbitrage subscription".to_string()
+                    } else {
+                        "No arbitrage subscription".to_string()
+                    }
+                } else {
+                    let symbol = client.context().command.maybe_arg::<String>("token").map(|s| s.to_uppercase());
+                    let removed = self.whale_monitor.unsubscribe(&chat, symbol.as_deref())?;
+                    if removed == 0 {
+                        "No matching whale subscriptions".to_string()
+                    } else {
+                        format!("✅ Removed {} whale subscription(s)", removed)
+                    }
+                };
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
+            "subscriptions" => {
+                let chat = command_chat(client.context()).ok_or("Subscriptions can only be set up in a chat")?;
+                let mut message = "📋 Subscriptions:\n\n".to_string();
+                let subscriptions = self.whale_monitor.subscriptions(&chat);
+                let arb_subscription = self.arb_monitor.subscription(&chat);
+                if let Some(min_spread) = arb_subscription {
+                    message.push_str(&format!("• Arbitrage ≥ {:.2}%\n", min_spread));
+                }
+                if subscriptions.is_empty() && arb_subscription.is_none() {
+                    message.push_str("No subscriptions set");
+                } else {
+                    for subscription in subscriptions {
+                        message.push_str(&format!(
+                            "• {} whales ≥ {:.2} ({})\n",
+                            subscription.token,
+                            subscription.threshold,
+                            match subscription.mode {
+                                DeliveryMode::Instant => "instant",
+                                DeliveryMode::Digest => "digest",
+                            }
+                        ));
+                    }
+                }
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
+            "quote" => {
+                let pay = client.context().command.maybe_arg::<String>("token").ok_or("Token to swap is required")?;
+                let pay = tokens::find(&pay).ok_or("Unsupported token")?;
+                let receive = client.context().command.maybe_arg::<String>("to_token").ok_or("Token to receive is required")?;
+                let receive = tokens::find(&receive).ok_or("Unsupported token")?;
+                let amount = client.context().command.maybe_arg::<String>("amount").ok_or("Amount is required")?;
+                let amount = parse_amount(&amount).ok_or("Invalid amount, use a positive number such as 100 or 1.5k")?;
+
+                let mut quotes = self.dex_source.quote(pay, amount, receive).await;
+                quotes.sort_by(|(_, a), (_, b)| {
+                    let a = a.as_ref().map(|q| q.amount_out).unwrap_or(-1.0);
+                    let b = b.as_ref().map(|q| q.amount_out).unwrap_or(-1.0);
+                    b.total_cmp(&a)
+                });
+
+                let mut message = format!("🔁 Swap Quote: {} {} → {}\n\n", amount, pay.symbol, receive.symbol);
+                match quotes.first() {
+                    Some((_, Ok(best))) => {
+                        message.push_str(&format!(
+                            "Best: {} — {:.6} {}\n• Price impact: {:.2}%\n• Fee: {:.2}%\n• Route: {}\n",
+                            best.dex.name(),
+                            best.amount_out,
+                            receive.symbol,
+                            best.price_impact,
+                            best.fee,
+                            best.route
+                        ));
+                    }
+                    _ => message.push_str("No DEX returned a quote\n"),
+                }
+                if quotes.len() > 1 {
+                    message.push_str("\nOther quotes:\n");
+                    for (dex, quote) in quotes.iter().skip(1) {
+                        match quote {
+                            Ok(q) => message.push_str(&format!(
+                                "• {}: {:.6} {} (impact {:.2}%, fee {:.2}%)\n",
+                                dex.name(),
+                                q.amount_out,
+                                receive.symbol,
+                                q.price_impact,
+                                q.fee
+                            )),
+                            Err(e) => message.push_str(&format!("• {}: unavailable ({})\n", dex.name(), e)),
+                        }
+                    }
+                }
+                message.push_str("\nQuotes only, no funds are moved.");
+
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
+            "arb" => {
+                let opportunities = self.arb_monitor.current_opportunities().await;
+                let mut message = format!(
+                    "⚖️ Arbitrage Opportunities (spread ≥ {:.2}% after fees)\n\n",
+                    self.arb_monitor.min_spread()
+                );
+                if opportunities.is_empty() {
+                    message.push_str("No opportunities right now");
+                } else {
+                    for opportunity in opportunities {
+                        message.push_str(&opportunity.describe());
+                        message.push('\n');
+                    }
+                }
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
+            "positions" => {
+                let owner = client.context().command.maybe_arg::<String>("principal").ok_or("Principal is required")?;
+                let owner = Principal::from_text(owner.trim()).map_err(|_| "Invalid principal")?;
+
+                let mut message = format!("💼 LP Positions for {}\n\n", owner);
+                let mut total = 0.0;
+                let mut found = false;
+                for (dex, positions) in self.dex_source.positions(owner).await {
+                    match positions {
+                        Ok(positions) => {
+                            for position in positions {
+                                found = true;
+                                total += position.value().unwrap_or_default();
+                                message.push_str(&self.describe_position(&owner, &position));
+                                message.push('\n');
+                            }
+                        }
+                        Err(e) => message.push_str(&format!("{}: unavailable ({})\n\n", dex.name(), e)),
+                    }
+                }
+                if found {
+                    message.push_str(&format!("Total value: ${:.2}", total));
+                } else {
+                    message.push_str("No liquidity positions found");
+                }
+
+                let response = client
+                    .send_text_message(message)
+                    .execute_then_return_message(|_, _| ());
+                Ok(SuccessResult { message: response })
+            },
             _ => Err("Invalid action".into()),
         }
     }
 }
 
 impl DexMonitor {
+    pub fn new(
+        whale_monitor: Arc<WhaleMonitor>,
+        arb_monitor: Arc<ArbMonitor>,
+        dex_source: Arc<DexSource>,
+        data_dir: &str,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            whale_monitor,
+            arb_monitor,
+            dex_source,
+            position_baselines: JsonStore::open(data_dir, "lp_baselines.json")?,
+        })
+    }
+
+    fn describe_position(&self, owner: &Principal, position: &LpPosition) -> String {
+        let mut text = format!(
+            "{} {}\n• {:.4} {} + {:.4} {}",
+            position.dex.name(),
+            position.id,
+            position.amount_0,
+            position.symbol_0,
+            position.amount_1,
+            position.symbol_1
+        );
+        match position.value() {
+            Some(value) => text.push_str(&format!(" (${:.2})\n", value)),
+            None => text.push_str(" (no price)\n"),
+        }
+
+        match position.fees {
+            Some((fees_0, fees_1)) => {
+                let label = if position.fees_compounded { "Fees earned (compounded)" } else { "Uncollected fees" };
+                text.push_str(&format!("• {}: {:.4} {} + {:.4} {}", label, fees_0, position.symbol_0, fees_1, position.symbol_1));
+                if let (Some(usd_0), Some(usd_1)) = (position.usd_0, position.usd_1) {
+                    text.push_str(&format!(" (${:.2})", fees_0 * usd_0 + fees_1 * usd_1));
+                }
+                text.push('\n');
+            }
+            None => text.push_str("• Fees: unavailable\n"),
+        }
+
+        // Impermanent loss compares the position with simply holding what it held when first seen
+        let key = format!("{}:{}:{}", owner, position.dex.name(), position.id);
+        let now = Utc::now();
+        let baseline = self.position_baselines.update(|baselines| {
+            let baseline = baselines.get(&key).cloned();
+            let entry = baselines.entry(key).or_insert(PositionBaseline {
+                amount_0: position.amount_0,
+                amount_1: position.amount_1,
+                timestamp: now,
+                last_seen: None,
+            });
+            entry.last_seen = Some(now);
+            prune_baselines(baselines, now);
+            baseline
+        });
+        match baseline {
+            Ok(Some(baseline)) => {
+                if let (Some(value), Some(usd_0), Some(usd_1)) = (position.value(), position.usd_0, position.usd_1) {
+                    let held = baseline.amount_0 * usd_0 + baseline.amount_1 * usd_1;
+                    if held > 0.0 {
+                        text.push_str(&format!(
+                            "• IL vs holding since first tracked ({}): {:+.2}%\n",
+                            baseline.timestamp.format("%Y-%m-%d %H:%M"),
+                            (value / held - 1.0) * 100.0
+                        ));
+                    }
+                }
+            }
+            Ok(None) => text.push_str("• IL vs holding: tracking from now\n"),
+            Err(e) => text.push_str(&format!("• IL vs holding: unavailable ({})\n", e)),
+        }
+        text
+    }
+
     fn definition() -> BotCommandDefinition {
         BotCommandDefinition {
             name: "dex".to_string(),
             description: Some("Monitor DEX whale movements and liquidity".to_string()),
             placeholder: Some("Fetching DEX data...".to_string()),
-            params: vec![BotCommandParam {
-                name: "action".to_string(),
-                description: Some("Choose what to monitor".to_string()),
-                placeholder: Some("Select whale or liquidity".to_string()),
-                required: true,
-                param_type: BotCommandParamType::StringParam(StringParam {
-                    min_length: 1,
-                    max_length: 20,
-                    choices: vec![
-                        BotCommandOptionChoice {
-                            name: "Whale Movements".to_string(),
-                            value: "whale".to_string(),
-                        },
-                        BotCommandOptionChoice {
-                            name: "Liquidity Tracking".to_string(),
-                            value: "liquidity".to_string(),
-                        },
-                    ],
-                    multi_line: false,
-                }),
-            }],
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("Choose what to monitor".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Whale Movements".to_string(),
+                                value: "whale".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Liquidity Tracking".to_string(),
+                                value: "liquidity".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Subscribe".to_string(),
+                                value: "subscribe".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Unsubscribe".to_string(),
+                                value: "unsubscribe".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "List Subscriptions".to_string(),
+                                value: "subscriptions".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Swap Quote".to_string(),
+                                value: "quote".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Arbitrage Opportunities".to_string(),
+                                value: "arb".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "LP Positions".to_string(),
+                                value: "positions".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "feed".to_string(),
+                    description: Some("Feed to subscribe to".to_string()),
+                    placeholder: Some("Select a feed".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Whale Transfers".to_string(),
+                                value: "whale".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Arbitrage Opportunities".to_string(),
+                                value: "arb".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "token".to_string(),
+                    description: Some("Token symbol".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Token amount, swap size or minimum arbitrage spread in %".to_string()),
+                    placeholder: Some("e.g. 10000".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "mode".to_string(),
+                    description: Some("Post each transfer or a periodic digest".to_string()),
+                    placeholder: Some("Select a delivery mode".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Instant".to_string(),
+                                value: "instant".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Digest".to_string(),
+                                value: "digest".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "to_token".to_string(),
+                    description: Some("Token to receive in a swap".to_string()),
+                    placeholder: Some("e.g. ckBTC".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "principal".to_string(),
+                    description: Some("Principal whose LP positions to look up".to_string()),
+                    placeholder: Some("e.g. aaaaa-aa".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 5,
+                        max_length: 70,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
             permissions: BotPermissions::from_message_permission(MessagePermission::Text),
             default_role: None,
             direct_messages: Some(true),
@@ -116,4 +559,37 @@ async fn get_liquidity_data() -> Result<DexData, String> {
                  • PancakeSwap: 100K BNB".to_string(),
         timestamp: chrono::Utc::now().timestamp(),
     })
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(last_seen: DateTime<Utc>) -> PositionBaseline {
+        PositionBaseline { amount_0: 1.0, amount_1: 2.0, timestamp: last_seen, last_seen: Some(last_seen) }
+    }
+
+    #[test]
+    fn unused_baselines_expire() {
+        let now = Utc::now();
+        let mut baselines = HashMap::from([
+            ("recent".to_string(), baseline(now - chrono::Duration::days(1))),
+            ("stale".to_string(), baseline(now - chrono::Duration::days(BASELINE_EXPIRY_DAYS + 1))),
+        ]);
+        prune_baselines(&mut baselines, now);
+        assert!(baselines.contains_key("recent"));
+        assert!(!baselines.contains_key("stale"));
+    }
+
+    #[test]
+    fn the_least_recently_seen_go_past_the_cap() {
+        let now = Utc::now();
+        let mut baselines: HashMap<String, PositionBaseline> = (0..MAX_BASELINES + 5)
+            .map(|i| (i.to_string(), baseline(now - chrono::Duration::minutes(i as i64))))
+            .collect();
+        prune_baselines(&mut baselines, now);
+        assert_eq!(baselines.len(), MAX_BASELINES);
+        assert!(baselines.contains_key("0"));
+        assert!(!baselines.contains_key(&(MAX_BASELINES + 4).to_string()));
+    }
+}
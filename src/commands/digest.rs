@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use chrono::{NaiveTime, Utc, Weekday};
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::{BotCommandContext, ChatRole};
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::commands::digest_scheduler::{parse_utc_offset, DigestScheduler};
+use crate::notifier::command_chat;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Digest::definition);
+
+pub struct Digest {
+    scheduler: Arc<DigestScheduler>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Digest {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let chat = command_chat(client.context()).ok_or("Digests can only be scheduled in a chat")?;
+        let frequency = client.context().command.arg::<String>("frequency").to_lowercase();
+
+        let message = match frequency.as_str() {
+            "daily" | "weekly" => {
+                let weekday = match frequency.as_str() {
+                    "weekly" => Some(
+                        client
+                            .context()
+                            .command
+                            .maybe_arg::<String>("day")
+                            .unwrap_or("mon".to_string())
+                            .parse::<Weekday>()
+                            .map_err(|_| "Invalid day, use e.g. mon or friday")?,
+                    ),
+                    _ => None,
+                };
+                let time = client.context().command.maybe_arg::<String>("time").unwrap_or("09:00".to_string());
+                let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| "Invalid time, use HH:MM e.g. 09:00")?;
+                let utc_offset = match client.context().command.maybe_arg::<String>("timezone") {
+                    Some(tz) => parse_utc_offset(&tz).ok_or("Invalid time zone, use UTC or an offset such as UTC+2")?,
+                    None => 0,
+                };
+                let schedule = self.scheduler.schedule(chat, weekday, time, utc_offset)?;
+                format!("📰 Market digest scheduled {}", schedule.describe())
+            }
+            "status" => match self.scheduler.schedule_for(&chat) {
+                Some(schedule) => format!("📰 Market digest is posted {}", schedule.describe()),
+                None => "No market digest scheduled in this chat".to_string(),
+            },
+            "off" => {
+                if self.scheduler.cancel(&chat)? {
+                    "✅ Market digest cancelled".to_string()
+                } else {
+                    "No market digest scheduled in this chat".to_string()
+                }
+            }
+            "now" => self.scheduler.compose(&chat, Utc::now() - chrono::Duration::days(1)).await?,
+            _ => return Err("Invalid frequency".into()),
+        };
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Digest {
+    pub fn new(scheduler: Arc<DigestScheduler>) -> Self {
+        Self { scheduler }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "digest".to_string(),
+            description: Some("Schedule a market digest in this chat".to_string()),
+            placeholder: Some("Updating digest...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "frequency".to_string(),
+                    description: Some("How often to post, or manage the schedule".to_string()),
+                    placeholder: Some("Select a frequency".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Daily".to_string(),
+                                value: "daily".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Weekly".to_string(),
+                                value: "weekly".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Show Schedule".to_string(),
+                                value: "status".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Post Now".to_string(),
+                                value: "now".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Turn Off".to_string(),
+                                value: "off".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "time".to_string(),
+                    description: Some("Time of day to post, defaults to 09:00".to_string()),
+                    placeholder: Some("e.g. 09:00".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 4,
+                        max_length: 5,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "timezone".to_string(),
+                    description: Some("UTC or an offset such as UTC+2, defaults to UTC".to_string()),
+                    placeholder: Some("e.g. UTC".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 9,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "day".to_string(),
+                    description: Some("Day of the week for weekly digests, defaults to mon".to_string()),
+                    placeholder: Some("e.g. mon".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 3,
+                        max_length: 9,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            // Scheduling posts into a chat is for its admins
+            default_role: Some(ChatRole::Admin),
+            direct_messages: Some(false),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::commands::alert_monitor::AlertMonitor;
+use crate::notifier::ChatNotifier;
+use crate::price_source;
+use crate::storage::JsonStore;
+use crate::watchlist;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Utc, Weekday};
+use oc_bots_sdk::types::Chat;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+// A digest missed by more than this, e.g. while the bot was down, is skipped rather than posted late
+const MAX_DELAY_SECS: i64 = 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DigestSchedule {
+    pub chat: Chat,
+    // Day of the week for weekly digests, daily when unset
+    pub weekday: Option<Weekday>,
+    pub time: NaiveTime,
+    // Minutes east of UTC the time is given in
+    pub utc_offset: i32,
+    // Unix seconds of the last occurrence posted, or of when the digest was scheduled
+    pub last_sent: i64,
+}
+
+impl DigestSchedule {
+    fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset * 60).unwrap_or(FixedOffset::east_opt(0).unwrap())
+    }
+
+    // The most recent time the digest was due at or before `now`
+    fn last_due(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let offset = self.offset();
+        let today = now.with_timezone(&offset).date_naive();
+        (0..8)
+            .filter_map(|days_back| today.checked_sub_days(chrono::Days::new(days_back)))
+            .filter(|date| self.weekday.is_none_or(|weekday| date.weekday() == weekday))
+            .filter_map(|date| date.and_time(self.time).and_local_timezone(offset).single())
+            .map(|due| due.with_timezone(&Utc))
+            .find(|due| *due <= now)
+    }
+
+    // How far back a digest looks for triggered alerts
+    fn period(&self) -> chrono::Duration {
+        match self.weekday {
+            Some(_) => chrono::Duration::days(7),
+            None => chrono::Duration::days(1),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let when = match self.weekday {
+            Some(weekday) => format!("every {}", weekday),
+            None => "daily".to_string(),
+        };
+        format!("{} at {} {}", when, self.time.format("%H:%M"), format_utc_offset(self.utc_offset))
+    }
+}
+
+// Posts a market digest (prices, 24h moves, top movers and triggered alerts) into chats on
+// the schedule their admins set
+pub struct DigestScheduler {
+    notifier: Arc<ChatNotifier>,
+    alert_monitor: Arc<AlertMonitor>,
+    symbols: Vec<String>,
+    schedules: JsonStore<Vec<DigestSchedule>>,
+}
+
+impl DigestScheduler {
+    pub fn new(
+        notifier: Arc<ChatNotifier>,
+        alert_monitor: Arc<AlertMonitor>,
+        symbols: Vec<String>,
+        data_dir: &str,
+    ) -> Result<Self, String> {
+        let symbols = symbols
+            .iter()
+            .filter_map(|symbol| {
+                let found = price_source::find_symbol(symbol);
+                if found.is_none() {
+                    warn!("Ignoring unsupported digest symbol {}", symbol);
+                }
+                found.map(|s| s.to_string())
+            })
+            .collect();
+        Ok(Self {
+            notifier,
+            alert_monitor,
+            symbols,
+            schedules: JsonStore::open(data_dir, "digests.json")?,
+        })
+    }
+
+    // Replaces any digest already scheduled in the chat
+    pub fn schedule(&self, chat: Chat, weekday: Option<Weekday>, time: NaiveTime, utc_offset: i32) -> Result<DigestSchedule, String> {
+        let schedule = DigestSchedule {
+            chat,
+            weekday,
+            time,
+            utc_offset,
+            last_sent: Utc::now().timestamp(),
+        };
+        self.schedules.update(|schedules| {
+            schedules.retain(|s| s.chat != chat);
+            schedules.push(schedule.clone());
+        })?;
+        Ok(schedule)
+    }
+
+    pub fn cancel(&self, chat: &Chat) -> Result<bool, String> {
+        self.schedules.update(|schedules| {
+            let before = schedules.len();
+            schedules.retain(|s| s.chat != *chat);
+            schedules.len() != before
+        })
+    }
+
+    pub fn schedule_for(&self, chat: &Chat) -> Option<DigestSchedule> {
+        self.schedules.read(|schedules| schedules.iter().find(|s| s.chat == *chat).cloned())
+    }
+
+    pub async fn compose(&self, chat: &Chat, since: DateTime<Utc>) -> Result<String, String> {
+        let table = watchlist::price_table(&self.symbols, None).await?;
+        let symbols: Vec<&str> = self.symbols.iter().map(|s| s.as_str()).collect();
+        let quotes = price_source::usd_quotes(&symbols).await?;
+
+        let mut moves: Vec<(&str, f64)> = quotes
+            .iter()
+            .filter_map(|(symbol, quote)| Some((*symbol, quote.change_24h?)))
+            .collect();
+        moves.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+        let movers: Vec<String> = moves
+            .iter()
+            .take(3)
+            .map(|(symbol, change)| format!("{} {} {:+.2}%", if *change >= 0.0 { "📈" } else { "📉" }, symbol, change))
+            .collect();
+
+        let mut message = format!("📰 Market digest\n\n{}\n", table);
+        if !movers.is_empty() {
+            message.push_str(&format!("Top movers (24h): {}\n", movers.join(", ")));
+        }
+        let fired = self.alert_monitor.fired_since(chat, since).await;
+        if fired.is_empty() {
+            message.push_str("\nNo alerts triggered since the last digest");
+        } else {
+            message.push_str(&format!("\n🔔 Alerts triggered since the last digest:\n{}", fired.join("\n")));
+        }
+        Ok(message)
+    }
+
+    pub async fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                self.post_due().await;
+            }
+        });
+    }
+
+    async fn post_due(&self) {
+        let now = Utc::now();
+        let due: Vec<(DigestSchedule, DateTime<Utc>)> = self.schedules.read(|schedules| {
+            schedules
+                .iter()
+                .filter_map(|s| Some((s.clone(), s.last_due(now)?)))
+                .filter(|(s, due)| due.timestamp() > s.last_sent && (now - *due).num_seconds() <= MAX_DELAY_SECS)
+                .collect()
+        });
+
+        for (schedule, due) in due {
+            let message = match self.compose(&schedule.chat, due - schedule.period()).await {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to compose digest for {:?}: {}", schedule.chat, e);
+                    continue;
+                }
+            };
+            // Without an API key for the chat, try again next minute
+            if !self.notifier.send_text(&schedule.chat, message) {
+                continue;
+            }
+            let result = self.schedules.update(|schedules| {
+                if let Some(s) = schedules.iter_mut().find(|s| s.chat == schedule.chat) {
+                    s.last_sent = due.timestamp();
+                }
+            });
+            if let Err(e) = result {
+                warn!("Failed to record digest for {:?}: {}", schedule.chat, e);
+            }
+        }
+    }
+}
+
+// Parse a UTC offset such as "UTC", "UTC+2", "UTC-05:30" or "+01:00" into minutes east of UTC
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let offset = offset.trim().to_uppercase();
+    let offset = offset.strip_prefix("UTC").or_else(|| offset.strip_prefix("GMT")).unwrap_or(&offset);
+    if offset.is_empty() {
+        return Some(0);
+    }
+    let (sign, rest) = match (offset.strip_prefix('+'), offset.strip_prefix('-')) {
+        (Some(rest), _) => (1, rest),
+        (_, Some(rest)) => (-1, rest),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let (hours, minutes) = (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?);
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+pub fn format_utc_offset(offset: i32) -> String {
+    match offset {
+        0 => "UTC".to_string(),
+        o if o % 60 == 0 => format!("UTC{:+}", o / 60),
+        o => format!("UTC{}{:02}:{:02}", if o < 0 { "-" } else { "+" }, o.abs() / 60, o.abs() % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(weekday: Option<Weekday>, time: &str, utc_offset: i32) -> DigestSchedule {
+        DigestSchedule {
+            chat: serde_json::from_str(r#"{"Group":"rrkah-fqaaa-aaaaa-aaaaq-cai"}"#).unwrap(),
+            weekday,
+            time: NaiveTime::parse_from_str(time, "%H:%M").unwrap(),
+            utc_offset,
+            last_sent: 0,
+        }
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn offsets_parse_in_each_form() {
+        assert_eq!(parse_utc_offset("UTC"), Some(0));
+        assert_eq!(parse_utc_offset(" gmt "), Some(0));
+        assert_eq!(parse_utc_offset("UTC+2"), Some(120));
+        assert_eq!(parse_utc_offset("UTC-05:30"), Some(-330));
+        assert_eq!(parse_utc_offset("+01:00"), Some(60));
+        assert_eq!(parse_utc_offset("UTC+14"), Some(14 * 60));
+    }
+
+    #[test]
+    fn invalid_offsets_are_rejected() {
+        for offset in ["2", "UTC+15", "UTC+2:60", "UTC+x", "UTC±2", "CET"] {
+            assert_eq!(parse_utc_offset(offset), None, "{}", offset);
+        }
+    }
+
+    #[test]
+    fn offsets_format_back_to_their_text() {
+        assert_eq!(format_utc_offset(0), "UTC");
+        assert_eq!(format_utc_offset(120), "UTC+2");
+        assert_eq!(format_utc_offset(-330), "UTC-05:30");
+        assert_eq!(format_utc_offset(345), "UTC+05:45");
+    }
+
+    #[test]
+    fn daily_digests_are_due_at_the_local_time() {
+        // 09:00 at UTC+2 is 07:00 UTC
+        let daily = schedule(None, "09:00", 120);
+        assert_eq!(daily.last_due(utc("2024-01-03T08:00:00Z")), Some(utc("2024-01-03T07:00:00Z")));
+        assert_eq!(daily.last_due(utc("2024-01-03T06:59:00Z")), Some(utc("2024-01-02T07:00:00Z")));
+    }
+
+    #[test]
+    fn weekly_digests_are_due_on_their_day() {
+        // 2024-01-03 is a Wednesday
+        let weekly = schedule(Some(Weekday::Fri), "18:00", 0);
+        assert_eq!(weekly.last_due(utc("2024-01-03T12:00:00Z")), Some(utc("2023-12-29T18:00:00Z")));
+        assert_eq!(weekly.last_due(utc("2024-01-05T18:00:00Z")), Some(utc("2024-01-05T18:00:00Z")));
+    }
+}
@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::price_history::{Ohlc, PriceHistory};
+use crate::price_source;
+use crate::util::parse_period;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(History::definition);
+
+pub struct History {
+    history: Arc<PriceHistory>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for History {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let symbol = client.context().command.arg::<String>("symbol");
+        let symbol = price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+        let period = client.context().command.maybe_arg::<String>("period").unwrap_or_else(|| "7d".to_string());
+        let duration = parse_period(&period).ok_or("Invalid period, use e.g. 24h, 7d or 4w")?;
+
+        let samples = self.history.series(symbol, duration.as_secs() as i64).await?;
+        let ohlc = Ohlc::from_samples(&samples).ok_or("No price history for that period yet")?;
+
+        let message = format!(
+            "📈 {} over {}\n\n\
+             Open: ${:.4}\n\
+             High: ${:.4} (<t:{}:f>)\n\
+             Low: ${:.4} (<t:{}:f>)\n\
+             Close: ${:.4}\n\
+             Change: {}\n\n\
+             {} samples from <t:{}:f> to <t:{}:f>",
+            symbol,
+            period,
+            ohlc.open,
+            ohlc.high.1,
+            ohlc.high.0,
+            ohlc.low.1,
+            ohlc.low.0,
+            ohlc.close,
+            ohlc.change().map_or("n/a".to_string(), |c| format!("{:+.2}%", c)),
+            ohlc.samples,
+            ohlc.start,
+            ohlc.end
+        );
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl History {
+    pub fn new(history: Arc<PriceHistory>) -> Self {
+        Self { history }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "history".to_string(),
+            description: Some("Price range and change over a period".to_string()),
+            placeholder: Some("Loading price history...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "symbol".to_string(),
+                    description: Some("Symbol to look up".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "period".to_string(),
+                    description: Some("How far back to look, defaults to 7d".to_string()),
+                    placeholder: Some("e.g. 24h, 7d, 4w".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 2,
+                        max_length: 5,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
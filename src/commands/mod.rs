@@ -1,4 +1,22 @@
 pub mod echo;
 pub mod price;
 pub mod dex;
-pub mod alert;
\ No newline at end of file
+pub mod alert;
+pub mod alert_monitor;
+pub mod whale_monitor;
+pub mod arb_monitor;
+pub mod tip;
+pub mod wallet;
+pub mod rain;
+pub mod tips;
+pub mod portfolio;
+pub mod watch;
+pub mod price_monitor;
+pub mod history;
+pub mod chart;
+pub mod ta;
+pub mod convert;
+pub mod peg_monitor;
+pub mod peg;
+pub mod digest_scheduler;
+pub mod digest;
\ No newline at end of file
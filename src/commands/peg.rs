@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::commands::peg_monitor::PegMonitor;
+use crate::notifier::command_chat;
+use crate::util::parse_amount;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Peg::definition);
+
+pub struct Peg {
+    monitor: Arc<PegMonitor>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Peg {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let action = client.context().command.maybe_arg::<String>("action").unwrap_or("view".to_string()).to_lowercase();
+
+        let message = match action.as_str() {
+            "view" => {
+                let threshold = command_chat(client.context())
+                    .and_then(|chat| self.monitor.subscription(&chat))
+                    .unwrap_or(self.monitor.threshold());
+                let statuses = self.monitor.statuses().await;
+                if statuses.is_empty() {
+                    "No stablecoin prices yet, try again in a few minutes".to_string()
+                } else {
+                    let lines: Vec<String> = statuses.iter().map(|s| s.describe(threshold)).collect();
+                    format!("🪙 Stablecoin pegs (⚠️ beyond ±{:.2}%)\n\n{}", threshold, lines.join("\n"))
+                }
+            }
+            "subscribe" => {
+                let chat = command_chat(client.context()).ok_or("Subscriptions can only be set up in a chat")?;
+                let threshold = match client.context().command.maybe_arg::<String>("threshold") {
+                    Some(t) => Some(parse_amount(&t).ok_or("Invalid threshold, use a % such as 0.5")?),
+                    None => None,
+                };
+                let threshold = self.monitor.subscribe(chat, threshold)?;
+                format!("🪙 Subscribed to depeg alerts when a stablecoin moves {:.2}% or more from $1", threshold)
+            }
+            "unsubscribe" => {
+                let chat = command_chat(client.context()).ok_or("Subscriptions can only be set up in a chat")?;
+                if self.monitor.unsubscribe(&chat)? {
+                    "✅ Removed depeg subscription".to_string()
+                } else {
+                    "No depeg subscription".to_string()
+                }
+            }
+            _ => return Err("Invalid action".into()),
+        };
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Peg {
+    pub fn new(monitor: Arc<PegMonitor>) -> Self {
+        Self { monitor }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "peg".to_string(),
+            description: Some("Stablecoin deviations from their peg".to_string()),
+            placeholder: Some("Checking pegs...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("View deviations or manage depeg alerts for this chat".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 11,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "View".to_string(),
+                                value: "view".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Subscribe".to_string(),
+                                value: "subscribe".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Unsubscribe".to_string(),
+                                value: "unsubscribe".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "threshold".to_string(),
+                    description: Some("Deviation in % that triggers an alert".to_string()),
+                    placeholder: Some("e.g. 0.5".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::dex_source::DexSource;
+use crate::notifier::ChatNotifier;
+use crate::price_source;
+use crate::storage::JsonStore;
+use crate::tokens;
+use oc_bots_sdk::types::Chat;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::error;
+
+// Stablecoins sold into ICP on each DEX to read their price. Small enough for price impact
+// not to matter, and the buy/sell midpoint cancels out the pool fee.
+const TRADE_SIZE: f64 = 100.0;
+
+// Sources that must agree before a stablecoin counts as off its peg, so one bad quote or
+// thin pool doesn't post an alert
+const MIN_SOURCES: usize = 2;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct PegSubscription {
+    chat: Chat,
+    // Percent deviation from $1 that gets posted
+    threshold: f64,
+    // Stablecoins last announced as off their peg in this chat
+    #[serde(default)]
+    depegged: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PegReading {
+    pub source: &'static str,
+    pub usd: f64,
+}
+
+impl PegReading {
+    // Percent away from $1
+    pub fn deviation(&self) -> f64 {
+        (self.usd - 1.0) * 100.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PegStatus {
+    pub symbol: &'static str,
+    pub readings: Vec<PegReading>,
+}
+
+impl PegStatus {
+    // Whether at least MIN_SOURCES readings put the stablecoin past the threshold on the same
+    // side of $1, or None when too few sources answered to tell
+    fn depegged(&self, threshold: f64) -> Option<bool> {
+        if self.readings.len() < MIN_SOURCES {
+            return None;
+        }
+        let below = self.readings.iter().filter(|r| r.deviation() <= -threshold).count();
+        let above = self.readings.iter().filter(|r| r.deviation() >= threshold).count();
+        Some(below.max(above) >= MIN_SOURCES)
+    }
+
+    pub fn describe(&self, threshold: f64) -> String {
+        let mut lines = format!(
+            "{}{}\n",
+            if self.depegged(threshold) == Some(true) { "⚠️ " } else { "" },
+            self.symbol
+        );
+        for reading in &self.readings {
+            lines.push_str(&format!(
+                "• {}: ${:.4} ({:+.2}%){}\n",
+                reading.source,
+                reading.usd,
+                reading.deviation(),
+                if reading.deviation().abs() >= threshold { " ⚠️" } else { "" }
+            ));
+        }
+        lines
+    }
+}
+
+// Tracks stablecoins against their $1 peg on CoinGecko and every configured DEX, and posts
+// into subscribed chats when one drifts past the chat's threshold or recovers
+pub struct PegMonitor {
+    dex_source: Arc<DexSource>,
+    notifier: Arc<ChatNotifier>,
+    tokens: Vec<String>,
+    threshold: f64,
+    subscriptions: JsonStore<Vec<PegSubscription>>,
+    statuses: Mutex<Vec<PegStatus>>,
+}
+
+impl PegMonitor {
+    pub fn new(
+        dex_source: Arc<DexSource>,
+        notifier: Arc<ChatNotifier>,
+        tokens: Vec<String>,
+        threshold: f64,
+        data_dir: &str,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            dex_source,
+            notifier,
+            tokens,
+            threshold,
+            subscriptions: JsonStore::open(data_dir, "peg_subscriptions.json")?,
+            statuses: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn subscribe(&self, chat: Chat, threshold: Option<f64>) -> Result<f64, String> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        self.subscriptions.update(|subscriptions| {
+            subscriptions.retain(|s| s.chat != chat);
+            // Current depegs are announced afresh against the new threshold
+            subscriptions.push(PegSubscription { chat, threshold, depegged: Vec::new() });
+        })?;
+        Ok(threshold)
+    }
+
+    pub fn unsubscribe(&self, chat: &Chat) -> Result<bool, String> {
+        self.subscriptions.update(|subscriptions| {
+            let before = subscriptions.len();
+            subscriptions.retain(|s| s.chat != *chat);
+            subscriptions.len() != before
+        })
+    }
+
+    pub fn subscription(&self, chat: &Chat) -> Option<f64> {
+        self.subscriptions
+            .read(|subscriptions| subscriptions.iter().find(|s| s.chat == *chat).map(|s| s.threshold))
+    }
+
+    // Readings from the latest scan
+    pub async fn statuses(&self) -> Vec<PegStatus> {
+        self.statuses.lock().await.clone()
+    }
+
+    pub async fn start_monitoring(self: Arc<Self>, poll_interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                self.scan().await;
+                tokio::time::sleep(poll_interval).await;
+            }
+        });
+    }
+
+    async fn scan(&self) {
+        // ICP's price turns each DEX's stablecoin/ICP pool price into dollars
+        let mut symbols: Vec<&str> = self.tokens.iter().map(|t| t.as_str()).collect();
+        symbols.push("ICP");
+        let quotes = match price_source::usd_quotes(&symbols).await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                error!("Failed to fetch stablecoin prices: {}", e);
+                Default::default()
+            }
+        };
+        let icp = tokens::find("ICP");
+        let icp_usd = quotes.get("ICP").map(|q| q.usd);
+
+        let mut statuses = Vec::new();
+        for symbol in &self.tokens {
+            let Some(token) = tokens::find(symbol) else {
+                continue;
+            };
+            let mut readings = Vec::new();
+            if let Some(quote) = quotes.get(token.symbol) {
+                readings.push(PegReading { source: "CoinGecko", usd: quote.usd });
+            }
+            if let (Some(icp), Some(icp_usd)) = (icp, icp_usd) {
+                for price in self.dex_source.pool_prices(token, icp, TRADE_SIZE).await {
+                    readings.push(PegReading {
+                        source: price.dex.name(),
+                        usd: (price.buy + price.sell) / 2.0 * icp_usd,
+                    });
+                }
+            }
+            if !readings.is_empty() {
+                statuses.push(PegStatus { symbol: token.symbol, readings });
+            }
+        }
+
+        *self.statuses.lock().await = statuses.clone();
+
+        // Post when a stablecoin crosses the chat's threshold in either direction. The state
+        // announced is kept with the subscription, and a stablecoin without enough readings
+        // keeps it, so restarts and failed fetches don't repeat an alert
+        let subscriptions = self.subscriptions.read(|subscriptions| subscriptions.clone());
+        for subscription in subscriptions {
+            let mut lines = Vec::new();
+            let mut depegged = subscription.depegged.clone();
+            for status in &statuses {
+                let Some(now_depegged) = status.depegged(subscription.threshold) else {
+                    continue;
+                };
+                let was_depegged = depegged.iter().any(|s| s == status.symbol);
+                match (was_depegged, now_depegged) {
+                    (false, true) => {
+                        depegged.push(status.symbol.to_string());
+                        lines.push(format!(
+                            "🚨 {} is off its peg by more than {:.2}%\n{}",
+                            status.symbol,
+                            subscription.threshold,
+                            status.describe(subscription.threshold)
+                        ));
+                    }
+                    (true, false) => {
+                        depegged.retain(|s| s != status.symbol);
+                        lines.push(format!(
+                            "✅ {} is back within {:.2}% of its peg\n{}",
+                            status.symbol,
+                            subscription.threshold,
+                            status.describe(subscription.threshold)
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            // Without an API key for the chat, the change is posted on a later scan
+            if lines.is_empty() || !self.notifier.send_text(&subscription.chat, lines.join("\n")) {
+                continue;
+            }
+            let result = self.subscriptions.update(|subscriptions| {
+                if let Some(s) = subscriptions.iter_mut().find(|s| s.chat == subscription.chat) {
+                    s.depegged = depegged;
+                }
+            });
+            if let Err(e) = result {
+                error!("Failed to record depeg state for {:?}: {}", subscription.chat, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(prices: &[f64]) -> PegStatus {
+        PegStatus {
+            symbol: "ckUSDC",
+            readings: prices.iter().map(|usd| PegReading { source: "test", usd: *usd }).collect(),
+        }
+    }
+
+    #[test]
+    fn one_outlier_is_not_a_depeg() {
+        assert_eq!(status(&[1.0, 0.95, 1.001]).depegged(0.5), Some(false));
+    }
+
+    #[test]
+    fn two_sources_past_the_threshold_are_a_depeg() {
+        assert_eq!(status(&[0.99, 0.992, 1.0]).depegged(0.5), Some(true));
+        assert_eq!(status(&[1.01, 1.006]).depegged(0.5), Some(true));
+    }
+
+    #[test]
+    fn sources_must_agree_on_the_direction() {
+        assert_eq!(status(&[0.99, 1.01, 1.0]).depegged(0.5), Some(false));
+    }
+
+    #[test]
+    fn a_single_source_cannot_decide() {
+        assert_eq!(status(&[0.9]).depegged(0.5), None);
+        assert_eq!(status(&[]).depegged(0.5), None);
+    }
+}
@@ -0,0 +1,373 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::{BotCommandContext, FileContent, MessageContentInitial, UserId};
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use crate::blob_storage::BlobStorage;
+use crate::export;
+use crate::ledger::Account;
+use crate::notifier::command_chat;
+use crate::portfolio::{Portfolio, Trade, TradeSide};
+use crate::price_source;
+use crate::tip_log::TipLog;
+use crate::util::parse_amount;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(PortfolioCommand::definition);
+
+pub struct PortfolioCommand {
+    portfolio: Arc<Portfolio>,
+    tip_log: Arc<TipLog>,
+    blob_storage: Arc<BlobStorage>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for PortfolioCommand {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let action = client.context().command.maybe_arg::<String>("action")
+            .unwrap_or_else(|| "view".to_string())
+            .to_lowercase();
+        let user = client.context().command.initiator;
+
+        let message = match action.as_str() {
+            "view" => self.describe(user).await?,
+            "add" => {
+                let account = parse_account(&client)?;
+                self.portfolio.add_account(user, &account)?;
+                format!("✅ Added `{}` to your portfolio", account)
+            },
+            "remove" => {
+                let account = parse_account(&client)?;
+                if self.portfolio.remove_account(user, &account)? {
+                    format!("🗑️ Removed `{}` from your portfolio", account)
+                } else {
+                    "That account isn't in your portfolio".to_string()
+                }
+            },
+            "buy" | "sell" => {
+                let side = if action == "buy" { TradeSide::Buy } else { TradeSide::Sell };
+                let trade = parse_trade(&client, side)?;
+                self.portfolio.record_trade(user, trade.clone())?;
+                format!(
+                    "📝 Recorded {} of {} {} at ${:.2} (${:.2} total)",
+                    action,
+                    trade.amount,
+                    trade.symbol,
+                    trade.price,
+                    trade.amount * trade.price
+                )
+            },
+            "pnl" => self.describe_pnl(user).await?,
+            "export" => return self.export(client, user).await,
+            _ => return Err("Invalid action".into()),
+        };
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl PortfolioCommand {
+    pub fn new(portfolio: Arc<Portfolio>, tip_log: Arc<TipLog>, blob_storage: Arc<BlobStorage>) -> Self {
+        Self { portfolio, tip_log, blob_storage }
+    }
+
+    // Trades and tips between the optional dates, sent as a CSV file
+    async fn export(&self, client: Client<AgentRuntime, BotCommandContext>, user: UserId) -> Result<SuccessResult, String> {
+        let chat = command_chat(client.context()).ok_or("Exports can only be sent in a chat")?;
+        let from = parse_date(&client, "from")?;
+        let to = parse_date(&client, "to")?;
+        let (start, end) = (from.unwrap_or(0), to.map_or(i64::MAX, |to| to + 24 * 60 * 60));
+        let trades: Vec<Trade> = self.portfolio
+            .trades(user)
+            .into_iter()
+            .filter(|t| t.timestamp >= start && t.timestamp < end)
+            .collect();
+        let tips = self.tip_log.between(user, start, end);
+        let csv = export::transactions_csv(user, &trades, &tips);
+
+        // Without dates the name covers the first record up to today
+        let now = chrono::Utc::now().timestamp();
+        let first = trades.iter().map(|t| t.timestamp).chain(tips.iter().map(|t| t.timestamp)).min();
+        let name = export::file_name(from.or(first).unwrap_or(now), to.unwrap_or(now));
+
+        let blob_reference = self.blob_storage.upload(csv.as_bytes(), "text/csv", vec![chat.canister_id().into()]).await?;
+        let content = MessageContentInitial::File(FileContent {
+            name,
+            caption: Some(format!("📄 {} trades, {} tips", trades.len(), tips.len())),
+            mime_type: "text/csv".to_string(),
+            file_size: csv.len() as u32,
+            blob_reference: Some(blob_reference),
+        });
+
+        let response = client
+            .send_message(content)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+
+    async fn describe(&self, user: UserId) -> Result<String, String> {
+        let accounts = self.portfolio.accounts(user);
+        if accounts.is_empty() {
+            return Ok("Your portfolio is empty, track a principal or account with `/portfolio add`".to_string());
+        }
+
+        let holdings = self.portfolio.holdings(user).await?;
+        let mut message = "📊 Your Portfolio:\n\n".to_string();
+        if holdings.is_empty() {
+            message.push_str("No balances on the tracked ledgers\n");
+        }
+
+        let mut total = 0.0;
+        let mut total_24h_ago = 0.0;
+        for holding in &holdings {
+            match (holding.value(), holding.quote) {
+                (Some(value), Some(quote)) => {
+                    total += value;
+                    total_24h_ago += holding.value_24h_ago().unwrap_or(value);
+                    message.push_str(&format!(
+                        "• {} {}: ${:.2}{}\n",
+                        holding.amount,
+                        holding.token.symbol,
+                        value,
+                        format_change(quote.change_24h)
+                    ));
+                }
+                _ => message.push_str(&format!(
+                    "• {} {}: price unavailable\n",
+                    holding.amount,
+                    holding.token.symbol
+                )),
+            }
+        }
+
+        let change = (total_24h_ago > 0.0).then(|| (total - total_24h_ago) / total_24h_ago * 100.0);
+        message.push_str(&format!(
+            "\nTotal: ${:.2}{}\nTracking {} account(s)",
+            total,
+            format_change(change),
+            accounts.len()
+        ));
+        Ok(message)
+    }
+
+    async fn describe_pnl(&self, user: UserId) -> Result<String, String> {
+        let positions = self.portfolio.positions(user).await?;
+        if positions.is_empty() {
+            return Ok("No manual holdings yet, record one with `/portfolio buy`".to_string());
+        }
+
+        let mut message = "📒 Manual Holdings (FIFO):\n\n".to_string();
+        let mut total_realised = 0.0;
+        let mut total_unrealised = 0.0;
+        for position in &positions {
+            total_realised += position.realised;
+            message.push_str(&format!("• {} {}", position.amount, position.symbol));
+            if position.amount > 0.0 {
+                message.push_str(&format!(" @ ${:.2} avg", position.cost / position.amount));
+            }
+            match (position.value(), position.unrealised()) {
+                (Some(value), Some(unrealised)) => {
+                    total_unrealised += unrealised;
+                    message.push_str(&format!(": ${:.2}, unrealised {}", value, format_usd(unrealised)));
+                }
+                _ => message.push_str(": price unavailable"),
+            }
+            message.push_str(&format!(", realised {}\n", format_usd(position.realised)));
+        }
+        message.push_str(&format!(
+            "\nRealised P&L: {}\nUnrealised P&L: {}",
+            format_usd(total_realised),
+            format_usd(total_unrealised)
+        ));
+        Ok(message)
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "portfolio".to_string(),
+            description: Some("Track your accounts and manual holdings with P&L".to_string()),
+            placeholder: Some("Loading portfolio...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("Choose portfolio action, defaults to view".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "View".to_string(),
+                                value: "view".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Add".to_string(),
+                                value: "add".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Remove".to_string(),
+                                value: "remove".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Buy".to_string(),
+                                value: "buy".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Sell".to_string(),
+                                value: "sell".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Profit & Loss".to_string(),
+                                value: "pnl".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Export CSV".to_string(),
+                                value: "export".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "account".to_string(),
+                    description: Some("Principal or ICRC-1 account to add or remove".to_string()),
+                    placeholder: Some("Principal or principal-checksum.subaccount".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 5,
+                        max_length: 200,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Amount bought or sold".to_string()),
+                    placeholder: Some("e.g. 0.1".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "symbol".to_string(),
+                    description: Some("Asset bought or sold".to_string()),
+                    placeholder: Some("e.g. BTC".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "price".to_string(),
+                    description: Some("USD price per unit".to_string()),
+                    placeholder: Some("e.g. 62000".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "from".to_string(),
+                    description: Some("First day to export".to_string()),
+                    placeholder: Some("YYYY-MM-DD".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 10,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "to".to_string(),
+                    description: Some("Last day to export".to_string()),
+                    placeholder: Some("YYYY-MM-DD".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 10,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            // Exports are sent as files
+            permissions: BotPermissions::default()
+                .with_message(&HashSet::from([MessagePermission::Text, MessagePermission::File])),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
+
+fn parse_account(client: &Client<AgentRuntime, BotCommandContext>) -> Result<Account, String> {
+    client.context().command.maybe_arg::<String>("account")
+        .ok_or("Account is required")?
+        .parse::<Account>()
+}
+
+fn parse_trade(client: &Client<AgentRuntime, BotCommandContext>, side: TradeSide) -> Result<Trade, String> {
+    let command = &client.context().command;
+    let amount = command.maybe_arg::<String>("amount").ok_or("Amount is required")?;
+    let amount = parse_amount(&amount).ok_or("Invalid amount, use a positive number such as 2.5")?;
+    let symbol = command.maybe_arg::<String>("symbol").ok_or("Symbol is required")?;
+    let symbol = price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+    let price = command.maybe_arg::<String>("price").ok_or("Price is required")?;
+    let price = parse_amount(&price).ok_or("Invalid price, use a positive USD price such as 12.5")?;
+    Ok(Trade {
+        side,
+        symbol: symbol.to_string(),
+        amount,
+        price,
+        timestamp: chrono::Utc::now().timestamp(),
+    })
+}
+
+// Start of a UTC day as a unix timestamp
+fn parse_date(client: &Client<AgentRuntime, BotCommandContext>, name: &str) -> Result<Option<i64>, String> {
+    let Some(date) = client.context().command.maybe_arg::<String>(name) else {
+        return Ok(None);
+    };
+    let date = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid {} date, use YYYY-MM-DD", name))?;
+    Ok(Some(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp()))
+}
+
+fn format_usd(value: f64) -> String {
+    if value < 0.0 {
+        format!("-${:.2}", -value)
+    } else {
+        format!("+${:.2}", value)
+    }
+}
+
+fn format_change(change: Option<f64>) -> String {
+    match change {
+        Some(change) => format!(" ({:+.2}% 24h)", change),
+        None => String::new(),
+    }
+}
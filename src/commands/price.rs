@@ -4,12 +4,18 @@ use oc_bots_sdk::api::definition::*;
 use oc_bots_sdk::types::BotCommandContext;
 use oc_bots_sdk_offchain::AgentRuntime;
 use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
 use std::sync::LazyLock;
-use reqwest;
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use crate::watchlist::{self, Watchlists};
 
 static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Price::definition);
 
-pub struct Price;
+pub struct Price {
+    watchlists: Arc<Watchlists>,
+    history: Arc<PriceHistory>,
+}
 
 #[async_trait]
 impl CommandHandler<AgentRuntime> for Price {
@@ -22,49 +28,40 @@ impl CommandHandler<AgentRuntime> for Price {
         client: Client<AgentRuntime, BotCommandContext>,
     ) -> Result<SuccessResult, String> {
         let symbol = client.context().command.arg::<String>("symbol").to_uppercase();
-        
-        // Use CoinGecko API to get price data
-        let http_client = reqwest::Client::new();
-        let url = format!(
-            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd",
-            match symbol.as_str() {
-                "BTC" => "bitcoin",
-                "ETH" => "ethereum",
-                "ICP" => "internet-computer",
-                _ => return Err("Unsupported cryptocurrency symbol".into()),
+        let sparkline = client.context().command.maybe_arg::<String>("sparkline")
+            .is_some_and(|s| s.eq_ignore_ascii_case("yes"));
+        if symbol == "WATCHLIST" {
+            let symbols = self.watchlists.symbols(client.context().command.initiator);
+            if symbols.is_empty() {
+                return Err("Your watchlist is empty, add a symbol with /watch add".into());
             }
-        );
-
-        match http_client.get(&url).send().await {
-            Ok(response) => {
-                match response.json::<serde_json::Value>().await {
-                    Ok(data) => {
-                        let price = match symbol.as_str() {
-                            "BTC" => data["bitcoin"]["usd"].as_f64(),
-                            "ETH" => data["ethereum"]["usd"].as_f64(),
-                            "ICP" => data["internet-computer"]["usd"].as_f64(),
-                            _ => None,
-                        };
+            let message = client
+                .send_text_message(format!("💰 Watchlist Prices:\n\n{}", watchlist::price_table(&symbols, sparkline.then_some(&*self.history)).await?))
+                .execute_then_return_message(|_, _| ());
+            return Ok(SuccessResult { message });
+        }
+        let symbol = price_source::find_symbol(&symbol).ok_or("Unsupported cryptocurrency symbol")?;
 
-                        match price {
-                            Some(p) => {
-                                let message = client
-                                    .send_text_message(format!("💰 Current {} price: ${:.2}", symbol, p))
-                                    .execute_then_return_message(|_, _| ());
-                                Ok(SuccessResult { message })
-                            },
-                            None => Err("Failed to parse price data".into()),
-                        }
-                    }
-                    Err(_) => Err("Failed to parse API response".into()),
-                }
+        let price = price_source::usd_price(symbol).await?;
+        let mut text = format!("💰 Current {} price: ${:.2}", symbol, price);
+        if sparkline {
+            match self.history.sparkline_24h(symbol) {
+                Some(line) => text.push_str(&format!("\n\nLast 24h: {}", line)),
+                None => text.push_str("\n\nNot enough recorded history for a sparkline yet"),
             }
-            Err(_) => Err("Failed to fetch price data".into()),
         }
+        let message = client
+            .send_text_message(text)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message })
     }
 }
 
 impl Price {
+    pub fn new(watchlists: Arc<Watchlists>, history: Arc<PriceHistory>) -> Self {
+        Self { watchlists, history }
+    }
+
     fn definition() -> BotCommandDefinition {
         BotCommandDefinition {
             name: "price".to_string(),
@@ -91,9 +88,27 @@ impl Price {
                             name: "Internet Computer (ICP)".to_string(),
                             value: "ICP".to_string(),
                         },
+                        BotCommandOptionChoice {
+                            name: "My watchlist".to_string(),
+                            value: "watchlist".to_string(),
+                        },
                     ],
                     multi_line: false,
                 }),
+            }, BotCommandParam {
+                name: "sparkline".to_string(),
+                description: Some("Include a sparkline of the last 24h".to_string()),
+                placeholder: Some("Select to include".to_string()),
+                required: false,
+                param_type: BotCommandParamType::StringParam(StringParam {
+                    min_length: 1,
+                    max_length: 3,
+                    choices: vec![BotCommandOptionChoice {
+                        name: "Yes".to_string(),
+                        value: "yes".to_string(),
+                    }],
+                    multi_line: false,
+                }),
             }],
             permissions: BotPermissions::from_message_permission(MessagePermission::Text),
             default_role: None,
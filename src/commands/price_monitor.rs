@@ -0,0 +1,35 @@
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+// Polls the price source and writes every symbol's price to the local history
+pub struct PriceMonitor {
+    history: Arc<PriceHistory>,
+}
+
+impl PriceMonitor {
+    pub fn new(history: Arc<PriceHistory>) -> Self {
+        Self { history }
+    }
+
+    pub async fn start_monitoring(&self, interval: Duration) {
+        let history = self.history.clone();
+        tokio::spawn(async move {
+            loop {
+                let symbols: Vec<&str> = price_source::symbols().collect();
+                match price_source::usd_quotes(&symbols).await {
+                    Ok(quotes) => {
+                        let now = chrono::Utc::now().timestamp();
+                        for (symbol, quote) in quotes {
+                            history.record(symbol, now, quote.usd);
+                        }
+                    }
+                    Err(e) => error!("Failed to poll prices: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,369 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::oc_api::actions::chat_events::{EventsPageArgs, EventsSelectionCriteria, Response as ChatEventsResponse};
+use oc_bots_sdk::types::{BotCommandContext, Chat, ChatEvent, UserId};
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::escrow::{Escrow, TipOutcome};
+use crate::notifier::{command_chat, command_message_id};
+use crate::tip_limits::{Reservation, TipLimits};
+use crate::storage::JsonStore;
+use crate::tokens::{self, Token, TOKENS};
+use crate::util::parse_amount;
+use crate::wallet::{command_memo, Wallet};
+use serde::{Deserialize, Serialize};
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Rain::definition);
+
+// Most recipients a single rain can be split between
+const MAX_RECIPIENTS: usize = 50;
+
+// Rains are remembered for well over the ledger's 24 hour deduplication window
+const RAIN_RETENTION_SECS: i64 = 2 * 24 * 60 * 60;
+
+// Who a rain was split between, fixed the first time the command runs so a retry
+// pays the same members the same share
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RainRecord {
+    message_id: u128,
+    token: String,
+    share: u128,
+    recipients: Vec<UserId>,
+    created: i64,
+}
+
+pub struct Rain {
+    wallet: Arc<Wallet>,
+    escrow: Arc<Escrow>,
+    limits: Arc<TipLimits>,
+    rains: JsonStore<Vec<RainRecord>>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Rain {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let sender = client.context().command.initiator;
+        let amount = parse_amount(&client.context().command.arg::<String>("amount"))
+            .ok_or("Invalid amount, use a positive number such as 100 or 1.5k")?;
+        let symbol = client.context().command.arg::<String>("token");
+        let token = tokens::find(&symbol).ok_or("Unsupported token")?;
+        let count = match client.context().command.maybe_arg::<String>("count") {
+            Some(c) => c.parse::<usize>().map_err(|_| "Invalid member count")?,
+            None => 10,
+        };
+        let confirmed = client.context().command.maybe_arg::<String>("confirm")
+            .is_some_and(|c| c.eq_ignore_ascii_case("yes"));
+        if count == 0 || count > MAX_RECIPIENTS {
+            return Err(format!("Member count must be between 1 and {}", MAX_RECIPIENTS));
+        }
+
+        let message_id = command_message_id(client.context()).ok_or("Rain can only be sent from a chat")?;
+        let rain = match self.rains.read(|rains| rains.iter().find(|r| r.message_id == message_id).cloned()) {
+            Some(rain) => rain,
+            None => {
+                let recipients = recent_active_users(&client, sender, count).await?;
+                if recipients.is_empty() {
+                    return Err("No recently active members to rain on".into());
+                }
+                self.remember(RainRecord {
+                    message_id,
+                    token: token.symbol.to_string(),
+                    share: token.to_units(amount) / recipients.len() as u128,
+                    recipients,
+                    created: chrono::Utc::now().timestamp(),
+                })?
+            }
+        };
+        let token = tokens::find(&rain.token).ok_or("Unsupported token")?;
+        let share = rain.share;
+
+        // Members an earlier attempt at this command already paid keep that payment
+        let mut paid = Vec::new();
+        let mut unpaid = Vec::new();
+        for recipient in &rain.recipients {
+            let memo = command_memo(message_id, Some(*recipient));
+            match self.escrow.completed(token, &memo) {
+                Some(TipOutcome::Sent(receipt)) => paid.push((*recipient, receipt.block_index, false)),
+                Some(TipOutcome::Escrowed(receipt, _)) => paid.push((*recipient, receipt.block_index, true)),
+                None => unpaid.push((*recipient, memo)),
+            }
+        }
+
+        // Limits and funds are checked for the rest before anything touches the ledger
+        let mut failed = Vec::new();
+        if !unpaid.is_empty() {
+            let total = share * unpaid.len() as u128;
+            let reservation = self.limits.reserve(sender, token, share, total, confirmed)?;
+            if let Err(e) = self.check_funds(sender, token, share, unpaid.len()).await {
+                self.limits.release(sender, token, &reservation, total);
+                return Err(e);
+            }
+            failed = self.pay(sender, token, share, &reservation, command_chat(client.context()), unpaid, &mut paid).await;
+        }
+
+        let mut message = format!(
+            "🌧️ @UserId({}) made it rain {} {} on {} members ({} {} each)\n\n",
+            sender,
+            token.from_units(share * paid.len() as u128),
+            token.symbol,
+            paid.len(),
+            token.from_units(share),
+            token.symbol
+        );
+        for (recipient, block_index, escrowed) in &paid {
+            message.push_str(&format!(
+                "• @UserId({}) (block {}){}\n",
+                recipient,
+                block_index,
+                if *escrowed { " ⏳ in escrow, use /wallet claim" } else { "" }
+            ));
+        }
+        for (recipient, error) in &failed {
+            message.push_str(&format!("• @UserId({}) failed: {}\n", recipient, error));
+        }
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Rain {
+    pub fn new(wallet: Arc<Wallet>, escrow: Arc<Escrow>, limits: Arc<TipLimits>, data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            wallet,
+            escrow,
+            limits,
+            rains: JsonStore::open(data_dir, "rains.json")?,
+        })
+    }
+
+    // Store a new rain, or return the one a concurrent attempt at the same command stored first
+    fn remember(&self, rain: RainRecord) -> Result<RainRecord, String> {
+        self.rains.update(|rains| remember_rain(rains, rain))
+    }
+
+    // Pay each member their share, returning the ones that failed
+    async fn pay(
+        &self,
+        sender: UserId,
+        token: &Token,
+        share: u128,
+        reservation: &Reservation,
+        chat: Option<Chat>,
+        recipients: Vec<(UserId, Vec<u8>)>,
+        paid: &mut Vec<(UserId, u64, bool)>,
+    ) -> Vec<(UserId, String)> {
+        let mut failed = Vec::new();
+        let mut uncounted = 0;
+        for (recipient, memo) in recipients {
+            let (receipt, escrowed) = match self.escrow.tip(sender, recipient, token, share, chat, memo).await {
+                Ok(TipOutcome::Sent(receipt)) => (receipt, false),
+                Ok(TipOutcome::Escrowed(receipt, _)) => (receipt, true),
+                Err(e) => {
+                    failed.push((recipient, e));
+                    uncounted += 1;
+                    continue;
+                }
+            };
+            // The ledger recognised a share the audit log missed, it was counted the first time
+            if receipt.duplicate {
+                uncounted += 1;
+            }
+            paid.push((recipient, receipt.block_index, escrowed));
+        }
+        if uncounted > 0 {
+            self.limits.release(sender, token, reservation, share * uncounted as u128);
+        }
+        failed
+    }
+
+    // Every recipient gets the same share, the sender pays one fee per transfer
+    async fn check_funds(&self, sender: UserId, token: &Token, share: u128, recipients: usize) -> Result<(), String> {
+        let fee = self.wallet.fee(token).await?;
+        if share <= fee {
+            return Err("Amount is too small to split between that many members".into());
+        }
+        let required = (share + fee) * recipients as u128;
+        let balance = self.wallet.balance(sender, token).await?;
+        if balance < required {
+            return Err(format!(
+                "Insufficient funds, raining on {} members needs {} {} including fees",
+                recipients,
+                token.from_units(required),
+                token.symbol
+            ));
+        }
+        Ok(())
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "rain".to_string(),
+            description: Some("Split a tip between the most recently active members".to_string()),
+            placeholder: Some("Making it rain...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Total amount to split".to_string()),
+                    placeholder: Some("e.g. 100".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "token".to_string(),
+                    description: Some("Token to rain".to_string()),
+                    placeholder: Some("Select a token".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: TOKENS
+                            .iter()
+                            .map(|t| BotCommandOptionChoice {
+                                name: format!("{} ({})", t.name, t.symbol),
+                                value: t.symbol.to_string(),
+                            })
+                            .collect(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "count".to_string(),
+                    description: Some("How many recently active members to include".to_string()),
+                    placeholder: Some("e.g. 10".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 3,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "confirm".to_string(),
+                    description: Some("Confirm a rain above the approval threshold after being asked".to_string()),
+                    placeholder: Some("Select to confirm".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 3,
+                        choices: vec![BotCommandOptionChoice {
+                            name: "Yes".to_string(),
+                            value: "yes".to_string(),
+                        }],
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text)
+                .with_chat(&HashSet::from([ChatPermission::ReadMessages])),
+            default_role: None,
+            direct_messages: Some(false),
+        }
+    }
+}
+
+// Authors of the latest messages in the chat, newest first, skipping bots and the sender
+async fn recent_active_users(
+    client: &Client<AgentRuntime, BotCommandContext>,
+    sender: UserId,
+    count: usize,
+) -> Result<Vec<UserId>, String> {
+    let response = client
+        .chat_events(EventsSelectionCriteria::Page(EventsPageArgs {
+            start_index: u32::MAX,
+            ascending: false,
+            max_messages: 200,
+            max_events: 500,
+        }))
+        .execute_async()
+        .await
+        .map_err(|(code, message)| format!("Failed to read chat events: {} {}", code, message))?;
+
+    let events = match response {
+        ChatEventsResponse::Success(result) => result.events,
+        other => return Err(format!("Failed to read chat events: {:?}", other)),
+    };
+
+    let bot_id = client.context().bot_id;
+    let mut seen = HashSet::new();
+    let mut users = Vec::new();
+    for event in events {
+        let ChatEvent::Message(message) = event.event else {
+            continue;
+        };
+        let author = message.sender;
+        if author == sender || author == bot_id || message.bot_context.is_some() {
+            continue;
+        }
+        if seen.insert(author) {
+            users.push(author);
+            if users.len() == count {
+                break;
+            }
+        }
+    }
+    Ok(users)
+}
+
+fn remember_rain(rains: &mut Vec<RainRecord>, rain: RainRecord) -> RainRecord {
+    rains.retain(|r| rain.created - r.created < RAIN_RETENTION_SECS);
+    if let Some(existing) = rains.iter().find(|r| r.message_id == rain.message_id) {
+        return existing.clone();
+    }
+    rains.push(rain.clone());
+    rain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn rain(message_id: u128, recipients: &[u8], created: i64) -> RainRecord {
+        RainRecord {
+            message_id,
+            token: "ICP".to_string(),
+            share: 1_000,
+            recipients: recipients.iter().map(|id| UserId::from(Principal::from_slice(&[*id]))).collect(),
+            created,
+        }
+    }
+
+    #[test]
+    fn a_retry_gets_the_recipients_chosen_first() {
+        let mut rains = Vec::new();
+        remember_rain(&mut rains, rain(7, &[1, 2, 3], 100));
+        let retried = remember_rain(&mut rains, rain(7, &[4, 5], 200));
+        assert_eq!(retried.recipients, rain(7, &[1, 2, 3], 100).recipients);
+        assert_eq!(retried.created, 100);
+        assert_eq!(rains.len(), 1);
+    }
+
+    #[test]
+    fn old_rains_are_forgotten() {
+        let mut rains = Vec::new();
+        remember_rain(&mut rains, rain(7, &[1], 0));
+        remember_rain(&mut rains, rain(8, &[2], RAIN_RETENTION_SECS + 1));
+        assert_eq!(rains.len(), 1);
+        assert_eq!(rains[0].message_id, 8);
+    }
+}
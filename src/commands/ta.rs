@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::time::Duration;
+use crate::commands::alert_monitor::format_window;
+use crate::indicators;
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use crate::util::parse_period;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(TechnicalAnalysis::definition);
+
+pub struct TechnicalAnalysis {
+    history: Arc<PriceHistory>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for TechnicalAnalysis {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let symbol = client.context().command.arg::<String>("symbol");
+        let symbol = price_source::find_symbol(&symbol).ok_or("Unsupported symbol")?;
+        let interval = client.context().command.maybe_arg::<String>("interval").unwrap_or_else(|| "4h".to_string());
+        let interval = parse_period(&interval).ok_or("Invalid interval, use e.g. 1h, 4h or 1d")?;
+        if interval < Duration::from_secs(15 * 60) {
+            return Err("Intervals must be at least 15m".into());
+        }
+
+        let candles = self
+            .history
+            .candles(symbol, interval.as_secs() as i64, candle_count(interval))
+            .await?;
+        let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+        let price = *closes.last().ok_or("No price history for that interval yet")?;
+
+        let rsi = indicators::rsi(&closes, 14);
+        let macd = indicators::macd(&closes, 12, 26, 9);
+        let bands = indicators::bollinger(&closes, 20, 2.0);
+        let sma = |period| indicators::sma(&closes, period).last().copied();
+        let ema = |period| indicators::ema(&closes, period).last().copied();
+        let (sma20, sma50, sma200) = (sma(20), sma(50), sma(200));
+        let (ema12, ema26) = (ema(12), ema(26));
+
+        let mut message = format!(
+            "📊 {} on {} candles\n\nPrice: ${:.4}\n",
+            symbol,
+            format_window(interval),
+            price
+        );
+        message.push_str(&match rsi {
+            Some(rsi) => format!("RSI(14): {:.1}{}\n", rsi, match rsi {
+                r if r >= 70.0 => " (overbought)",
+                r if r <= 30.0 => " (oversold)",
+                _ => "",
+            }),
+            None => "RSI(14): n/a\n".to_string(),
+        });
+        message.push_str(&match &macd {
+            Some(m) => format!("MACD(12,26,9): {:.4}, signal {:.4}, histogram {:+.4}\n", m.macd, m.signal, m.histogram),
+            None => "MACD(12,26,9): n/a\n".to_string(),
+        });
+        message.push_str(&match &bands {
+            Some(b) => format!("Bollinger(20, 2σ): ${:.4} / ${:.4} / ${:.4}\n", b.upper, b.middle, b.lower),
+            None => "Bollinger(20, 2σ): n/a\n".to_string(),
+        });
+        message.push_str(&format!(
+            "SMA 20/50/200: {} / {} / {}\nEMA 12/26: {} / {}\n",
+            level(sma20),
+            level(sma50),
+            level(sma200),
+            level(ema12),
+            level(ema26)
+        ));
+
+        // One vote per signal that could be computed
+        let votes = [
+            sma50.map(|s| price > s),
+            sma50.zip(sma200).map(|(fast, slow)| fast > slow),
+            ema12.zip(ema26).map(|(fast, slow)| fast > slow),
+            macd.as_ref().map(|m| m.histogram > 0.0),
+        ];
+        let score: i32 = votes.iter().flatten().map(|bullish| if *bullish { 1 } else { -1 }).sum();
+        let verdict = match score {
+            s if s >= 2 => "🟢 Bullish",
+            s if s <= -2 => "🔴 Bearish",
+            _ => "⚪ Neutral",
+        };
+        message.push_str(&format!(
+            "\nTrend: {} ({} of {} signals bullish)\n\n{} candles from <t:{}:f>",
+            verdict,
+            votes.iter().flatten().filter(|bullish| **bullish).count(),
+            votes.iter().flatten().count(),
+            candles.len(),
+            candles[0].start
+        ));
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl TechnicalAnalysis {
+    pub fn new(history: Arc<PriceHistory>) -> Self {
+        Self { history }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "ta".to_string(),
+            description: Some("RSI, MACD, Bollinger bands, moving averages and trend".to_string()),
+            placeholder: Some("Crunching indicators...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "symbol".to_string(),
+                    description: Some("Symbol to analyse".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "interval".to_string(),
+                    description: Some("Candle interval, defaults to 4h".to_string()),
+                    placeholder: Some("e.g. 1h, 4h, 1d".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 2,
+                        max_length: 5,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
+
+// Enough candles for SMA(200), limited to the history CoinGecko serves at that granularity
+fn candle_count(interval: Duration) -> usize {
+    let days = if interval < Duration::from_secs(60 * 60) {
+        1
+    } else if interval < Duration::from_secs(24 * 60 * 60) {
+        90
+    } else {
+        365
+    };
+    (days * 24 * 60 * 60 / interval.as_secs()).min(201) as usize
+}
+
+fn level(value: Option<f64>) -> String {
+    value.map(|v| format!("${:.4}", v)).unwrap_or_else(|| "n/a".to_string())
+}
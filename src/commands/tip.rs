@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::{BotCommandContext, Chat, UserId};
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::escrow::{Escrow, TipOutcome};
+use crate::notifier::{command_chat, command_message_id};
+use crate::tip_limits::TipLimits;
+use crate::tokens::{self, Token, TOKENS};
+use crate::util::parse_amount;
+use crate::wallet::command_memo;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Tip::definition);
+
+pub struct Tip {
+    escrow: Arc<Escrow>,
+    limits: Arc<TipLimits>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Tip {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let sender = client.context().command.initiator;
+        let recipient = client.context().command.arg::<UserId>("user");
+        let amount = parse_amount(&client.context().command.arg::<String>("amount"))
+            .ok_or("Invalid amount, use a positive number such as 5 or 1.5k")?;
+        let symbol = client.context().command.arg::<String>("token");
+        let token = tokens::find(&symbol).ok_or("Unsupported token")?;
+        let confirmed = client.context().command.maybe_arg::<String>("confirm")
+            .is_some_and(|c| c.eq_ignore_ascii_case("yes"));
+
+        if recipient == sender {
+            return Err("You can't tip yourself".into());
+        }
+
+        let message_id = command_message_id(client.context()).ok_or("Tips can only be sent from a chat")?;
+        let memo = command_memo(message_id, None);
+
+        let units = token.to_units(amount);
+        if units == 0 {
+            return Err(format!("Amount is below the smallest unit of {}", token.symbol));
+        }
+        // A retry of a tip that already went through gets the earlier receipt, without
+        // counting against the limits again
+        let outcome = match self.escrow.completed(token, &memo) {
+            Some(outcome) => outcome,
+            None => self.send(sender, recipient, token, units, confirmed, command_chat(client.context()), memo).await?,
+        };
+
+        let message = match outcome {
+            TipOutcome::Sent(receipt) => format!(
+                "💸 @UserId({}) tipped @UserId({}) {} {}\n\nBlock: {} • Fee: {} {}",
+                sender,
+                recipient,
+                amount,
+                token.symbol,
+                receipt.block_index,
+                token.from_units(receipt.fee),
+                token.symbol
+            ),
+            TipOutcome::Escrowed(receipt, entry) => format!(
+                "💸 @UserId({}) tipped @UserId({}) {} {}\n\n\
+                 ⏳ @UserId({}) hasn't set up a wallet yet, so the tip is held in escrow. \
+                 Use /wallet claim <t:{}:R> to receive it, otherwise it goes back to the sender.\n\n\
+                 Block: {} • Fee: {} {}",
+                sender,
+                recipient,
+                amount,
+                token.symbol,
+                recipient,
+                entry.expires,
+                receipt.block_index,
+                token.from_units(receipt.fee),
+                token.symbol
+            ),
+        };
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Tip {
+    pub fn new(escrow: Arc<Escrow>, limits: Arc<TipLimits>) -> Self {
+        Self { escrow, limits }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send(
+        &self,
+        sender: UserId,
+        recipient: UserId,
+        token: &Token,
+        units: u128,
+        confirmed: bool,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TipOutcome, String> {
+        let reservation = self.limits.reserve(sender, token, units, units, confirmed)?;
+        let outcome = match self.escrow.tip(sender, recipient, token, units, chat, memo).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                self.limits.release(sender, token, &reservation, units);
+                return Err(e);
+            }
+        };
+
+        // The ledger recognised a retry the audit log missed, it was counted the first time
+        if let TipOutcome::Sent(receipt) | TipOutcome::Escrowed(receipt, _) = &outcome {
+            if receipt.duplicate {
+                self.limits.release(sender, token, &reservation, units);
+            }
+        }
+        Ok(outcome)
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "tip".to_string(),
+            description: Some("Tip another user from your wallet".to_string()),
+            placeholder: Some("Sending tip...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "user".to_string(),
+                    description: Some("User to tip".to_string()),
+                    placeholder: Some("Select a user".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::UserParam,
+                },
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Amount to tip".to_string()),
+                    placeholder: Some("e.g. 5".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "token".to_string(),
+                    description: Some("Token to tip".to_string()),
+                    placeholder: Some("Select a token".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: TOKENS
+                            .iter()
+                            .map(|t| BotCommandOptionChoice {
+                                name: format!("{} ({})", t.name, t.symbol),
+                                value: t.symbol.to_string(),
+                            })
+                            .collect(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "confirm".to_string(),
+                    description: Some("Confirm a tip above the approval threshold after being asked".to_string()),
+                    placeholder: Some("Select to confirm".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 3,
+                        choices: vec![BotCommandOptionChoice {
+                            name: "Yes".to_string(),
+                            value: "yes".to_string(),
+                        }],
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(false),
+        }
+    }
+}
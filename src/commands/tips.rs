@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::notifier::command_chat;
+use crate::tip_log::{LeaderboardEntry, TipLog};
+use crate::tokens;
+use crate::util::format_amount;
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Tips::definition);
+
+const HISTORY_LIMIT: usize = 20;
+const LEADERBOARD_LIMIT: usize = 5;
+
+pub struct Tips {
+    tip_log: Arc<TipLog>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Tips {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let action = client.context().command.arg::<String>("action").to_lowercase();
+        let user = client.context().command.initiator;
+
+        let message = match action.as_str() {
+            "history" => {
+                let records = self.tip_log.history(user, HISTORY_LIMIT);
+                if records.is_empty() {
+                    "You haven't sent or received any tips yet".to_string()
+                } else {
+                    let mut message = "🧾 Your Recent Tips:\n\n".to_string();
+                    for record in records {
+                        let amount = format_tip_amount(&record.token, record.amount);
+                        let line = if record.sender == user {
+                            format!("↗️ {} to @UserId({})", amount, record.recipient)
+                        } else {
+                            format!("↙️ {} from @UserId({})", amount, record.sender)
+                        };
+                        message.push_str(&format!(
+                            "• {}{} (block {}, <t:{}:R>)\n",
+                            line,
+                            if record.refunded { " ↩️" } else if record.escrowed { " ⏳" } else { "" },
+                            record.block_index,
+                            record.timestamp
+                        ));
+                    }
+                    message
+                }
+            },
+            "leaderboard" => {
+                let chat = command_chat(client.context()).ok_or("Leaderboards are only available in chats")?;
+                let period = client.context().command.maybe_arg::<String>("period")
+                    .unwrap_or_else(|| "week".to_string())
+                    .to_lowercase();
+                let days = match period.as_str() {
+                    "week" => 7,
+                    "month" => 30,
+                    _ => return Err("Period must be week or month".into()),
+                };
+                let since = chrono::Utc::now().timestamp() - days * 24 * 60 * 60;
+                let (senders, receivers) = self.tip_log.leaderboard(&chat, since, LEADERBOARD_LIMIT);
+                if senders.is_empty() {
+                    format!("No tips in this chat in the last {}", period)
+                } else {
+                    format!(
+                        "🏆 Tip Leaderboard (last {})\n\nTop tippers:\n{}\nTop receivers:\n{}",
+                        period,
+                        format_leaderboard(&senders),
+                        format_leaderboard(&receivers)
+                    )
+                }
+            },
+            _ => return Err("Invalid action".into()),
+        };
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Tips {
+    pub fn new(tip_log: Arc<TipLog>) -> Self {
+        Self { tip_log }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "tips".to_string(),
+            description: Some("Show your tip history or the chat's tip leaderboard".to_string()),
+            placeholder: Some("Looking up tips...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("Choose what to show".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 15,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "History".to_string(),
+                                value: "history".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Leaderboard".to_string(),
+                                value: "leaderboard".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "period".to_string(),
+                    description: Some("Leaderboard period, defaults to week".to_string()),
+                    placeholder: Some("Select a period".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Week".to_string(),
+                                value: "week".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Month".to_string(),
+                                value: "month".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
+
+// Tip amounts are kept in base units
+fn format_tip_amount(symbol: &str, amount: u128) -> String {
+    match tokens::find(symbol) {
+        Some(token) => format!("{} {}", format_amount(token.from_units(amount), token.decimals), token.symbol),
+        None => format!("{} {}", amount, symbol),
+    }
+}
+
+fn format_leaderboard(entries: &[LeaderboardEntry]) -> String {
+    let mut lines = String::new();
+    let mut rank = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        if i == 0 || entries[i - 1].token != entry.token {
+            rank = 0;
+        }
+        rank += 1;
+        lines.push_str(&format!(
+            "{}. @UserId({}) {} ({} tips)\n",
+            rank,
+            entry.user,
+            format_tip_amount(&entry.token, entry.amount),
+            entry.count
+        ));
+    }
+    lines
+}
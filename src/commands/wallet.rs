@@ -0,0 +1,222 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::escrow::Escrow;
+use crate::ledger::Account;
+use crate::notifier::{command_chat, command_message_id};
+use crate::tokens::{self, Token};
+use crate::util::parse_amount;
+use crate::wallet::{command_memo, Wallet};
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(WalletCommand::definition);
+
+pub struct WalletCommand {
+    wallet: Arc<Wallet>,
+    escrow: Arc<Escrow>,
+    tokens: Vec<&'static Token>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for WalletCommand {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let action = client.context().command.arg::<String>("action").to_lowercase();
+        let user = client.context().command.initiator;
+
+        // Using the wallet at all counts as setting it up, so release any tips held in escrow
+        let claims = self.escrow.register(user).await?;
+        let mut claimed = String::new();
+        for (entry, result) in &claims {
+            let Some(token) = tokens::find(&entry.token) else {
+                continue;
+            };
+            match result {
+                Ok(receipt) => claimed.push_str(&format!(
+                    "• {} {} from @UserId({}) (block {})\n",
+                    token.from_units(entry.amount - receipt.fee),
+                    token.symbol,
+                    entry.sender,
+                    receipt.block_index
+                )),
+                Err(e) => claimed.push_str(&format!(
+                    "• {} {} from @UserId({}) failed, will retry: {}\n",
+                    token.from_units(entry.amount),
+                    token.symbol,
+                    entry.sender,
+                    e
+                )),
+            }
+        }
+        if !claimed.is_empty() {
+            claimed = format!("🎁 Claimed tips held in escrow:\n\n{}\n", claimed);
+        }
+
+        let message = match action.as_str() {
+            "claim" => {
+                if claimed.is_empty() {
+                    "🎁 You have no tips waiting in escrow".to_string()
+                } else {
+                    std::mem::take(&mut claimed)
+                }
+            },
+            "deposit" => {
+                format!(
+                    "📥 Your deposit account\n\nSend any of {} to:\n`{}`\n\nFunds are held by the bot in a subaccount that only you can spend from.",
+                    self.tokens.iter().map(|t| t.symbol).collect::<Vec<_>>().join(", "),
+                    self.wallet.user_account(user)
+                )
+            },
+            "balance" => {
+                let mut message = "👛 Your Wallet:\n\n".to_string();
+                for token in &self.tokens {
+                    match self.wallet.balance(user, token).await {
+                        Ok(balance) => message.push_str(&format!("• {} {}\n", token.from_units(balance), token.symbol)),
+                        Err(e) => message.push_str(&format!("• {}: unavailable ({})\n", token.symbol, e)),
+                    }
+                }
+                message
+            },
+            "withdraw" => {
+                let amount = client.context().command.maybe_arg::<String>("amount").ok_or("Amount is required")?;
+                let amount = parse_amount(&amount).ok_or("Invalid amount, use a positive number such as 5 or 1.5k")?;
+                let symbol = client.context().command.maybe_arg::<String>("token").ok_or("Token is required")?;
+                let token = self.find_token(&symbol)?;
+                let to = client.context().command.maybe_arg::<String>("account")
+                    .ok_or("Destination account is required")?
+                    .parse::<Account>()?;
+
+                let chat = command_chat(client.context());
+                let message_id = command_message_id(client.context()).ok_or("Withdrawals can only be made from a chat")?;
+                let memo = command_memo(message_id, None);
+                let receipt = self.wallet
+                    .withdraw(user, token, token.to_units(amount), to.clone(), chat, memo)
+                    .await?;
+                format!(
+                    "📤 Withdrew {} {} to `{}`\n\nReceived: {} {} • Fee: {} {} • Block: {}",
+                    amount,
+                    token.symbol,
+                    to,
+                    token.from_units(token.to_units(amount) - receipt.fee),
+                    token.symbol,
+                    token.from_units(receipt.fee),
+                    token.symbol,
+                    receipt.block_index
+                )
+            },
+            _ => return Err("Invalid action".into()),
+        };
+        let message = format!("{}{}", claimed, message);
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl WalletCommand {
+    pub fn new(wallet: Arc<Wallet>, escrow: Arc<Escrow>, symbols: &[String]) -> Self {
+        Self {
+            wallet,
+            escrow,
+            tokens: symbols.iter().filter_map(|s| tokens::find(s)).collect(),
+        }
+    }
+
+    fn find_token(&self, symbol: &str) -> Result<&'static Token, String> {
+        self.tokens
+            .iter()
+            .find(|t| t.symbol.eq_ignore_ascii_case(symbol))
+            .copied()
+            .ok_or_else(|| "Unsupported token".to_string())
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "wallet".to_string(),
+            description: Some("Deposit, check, withdraw and claim your tipping funds".to_string()),
+            placeholder: Some("Checking wallet...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("Choose wallet action".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: true,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "Deposit".to_string(),
+                                value: "deposit".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Balance".to_string(),
+                                value: "balance".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Withdraw".to_string(),
+                                value: "withdraw".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Claim".to_string(),
+                                value: "claim".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "amount".to_string(),
+                    description: Some("Amount to withdraw, the ledger fee is taken from it".to_string()),
+                    placeholder: Some("e.g. 10".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 20,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "token".to_string(),
+                    description: Some("Token to withdraw".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "account".to_string(),
+                    description: Some("ICRC-1 account to withdraw to".to_string()),
+                    placeholder: Some("Principal or principal-checksum.subaccount".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 5,
+                        max_length: 200,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
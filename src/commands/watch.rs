@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use oc_bots_sdk::api::command::{CommandHandler, SuccessResult};
+use oc_bots_sdk::api::definition::*;
+use oc_bots_sdk::types::BotCommandContext;
+use oc_bots_sdk_offchain::AgentRuntime;
+use oc_bots_sdk::oc_api::client::Client;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use crate::watchlist::{self, Watchlists};
+
+static DEFINITION: LazyLock<BotCommandDefinition> = LazyLock::new(Watch::definition);
+
+pub struct Watch {
+    watchlists: Arc<Watchlists>,
+    history: Arc<PriceHistory>,
+}
+
+#[async_trait]
+impl CommandHandler<AgentRuntime> for Watch {
+    fn definition(&self) -> &BotCommandDefinition {
+        &DEFINITION
+    }
+
+    async fn execute(
+        &self,
+        client: Client<AgentRuntime, BotCommandContext>,
+    ) -> Result<SuccessResult, String> {
+        let action = client.context().command.maybe_arg::<String>("action")
+            .unwrap_or_else(|| "view".to_string())
+            .to_lowercase();
+        let user = client.context().command.initiator;
+
+        let message = match action.as_str() {
+            "view" => {
+                let symbols = self.watchlists.symbols(user);
+                let sparkline = client.context().command.maybe_arg::<String>("sparkline")
+                    .is_some_and(|s| s.eq_ignore_ascii_case("yes"));
+                if symbols.is_empty() {
+                    "Your watchlist is empty, add a symbol with `/watch add`".to_string()
+                } else {
+                    format!("👀 Your Watchlist:\n\n{}", watchlist::price_table(&symbols, sparkline.then_some(&*self.history)).await?)
+                }
+            },
+            "add" => {
+                let symbol = client.context().command.maybe_arg::<String>("symbol").ok_or("Symbol is required")?;
+                let symbol = self.watchlists.add(user, &symbol)?;
+                format!("✅ Added {} to your watchlist", symbol)
+            },
+            "remove" => {
+                let symbol = client.context().command.maybe_arg::<String>("symbol").ok_or("Symbol is required")?;
+                match self.watchlists.remove(user, &symbol)? {
+                    Some(removed) => format!("🗑️ Removed {} from your watchlist", removed),
+                    None => format!("{} isn't on your watchlist", price_source::find_symbol(&symbol).unwrap_or(symbol.as_str())),
+                }
+            },
+            _ => return Err("Invalid action".into()),
+        };
+
+        let response = client
+            .send_text_message(message)
+            .execute_then_return_message(|_, _| ());
+        Ok(SuccessResult { message: response })
+    }
+}
+
+impl Watch {
+    pub fn new(watchlists: Arc<Watchlists>, history: Arc<PriceHistory>) -> Self {
+        Self { watchlists, history }
+    }
+
+    fn definition() -> BotCommandDefinition {
+        BotCommandDefinition {
+            name: "watch".to_string(),
+            description: Some("Manage your watchlist and see its prices".to_string()),
+            placeholder: Some("Loading watchlist...".to_string()),
+            params: vec![
+                BotCommandParam {
+                    name: "action".to_string(),
+                    description: Some("Choose watchlist action, defaults to view".to_string()),
+                    placeholder: Some("Select an action".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: vec![
+                            BotCommandOptionChoice {
+                                name: "View".to_string(),
+                                value: "view".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Add".to_string(),
+                                value: "add".to_string(),
+                            },
+                            BotCommandOptionChoice {
+                                name: "Remove".to_string(),
+                                value: "remove".to_string(),
+                            },
+                        ],
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "symbol".to_string(),
+                    description: Some("Symbol to add or remove".to_string()),
+                    placeholder: Some("e.g. ICP".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 10,
+                        choices: Vec::new(),
+                        multi_line: false,
+                    }),
+                },
+                BotCommandParam {
+                    name: "sparkline".to_string(),
+                    description: Some("Include a sparkline of the last 24h".to_string()),
+                    placeholder: Some("Select to include".to_string()),
+                    required: false,
+                    param_type: BotCommandParamType::StringParam(StringParam {
+                        min_length: 1,
+                        max_length: 3,
+                        choices: vec![BotCommandOptionChoice {
+                            name: "Yes".to_string(),
+                            value: "yes".to_string(),
+                        }],
+                        multi_line: false,
+                    }),
+                },
+            ],
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            default_role: None,
+            direct_messages: Some(true),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::ledger::{self, LedgerTransfer};
+use crate::notifier::ChatNotifier;
+use crate::storage::JsonStore;
+use crate::tokens;
+use oc_bots_sdk::types::Chat;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+// Ledger transactions fetched per page while catching up to the newest block
+const PAGE_SIZE: u32 = 100;
+// Pages read per ledger on each poll before skipping ahead
+const MAX_PAGES: u32 = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryMode {
+    Instant,
+    Digest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhaleSubscription {
+    pub chat: Chat,
+    pub token: String,
+    pub threshold: f64,
+    pub mode: DeliveryMode,
+    pending: Vec<LedgerTransfer>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct WhaleBook {
+    subscriptions: Vec<WhaleSubscription>,
+    // Last block processed on each watched ledger, by token symbol
+    last_seen: HashMap<String, u64>,
+}
+
+impl WhaleBook {
+    // Move each ledger's last seen block forward and collect the messages due: instant
+    // alerts for every qualifying transfer, digests only when they are flushed
+    fn apply_scan(
+        &mut self,
+        new_transfers: &HashMap<String, Vec<LedgerTransfer>>,
+        newest: HashMap<String, u64>,
+        flush_digests: bool,
+    ) -> Vec<(Chat, String)> {
+        for (symbol, index) in newest {
+            let seen = self.last_seen.entry(symbol).or_insert(index);
+            *seen = (*seen).max(index);
+        }
+        // Drop positions for ledgers nobody watches any more, so resubscribing starts fresh
+        let subscriptions = &self.subscriptions;
+        self.last_seen.retain(|symbol, _| subscriptions.iter().any(|s| s.token == *symbol));
+
+        let mut messages = Vec::new();
+        for subscription in self.subscriptions.iter_mut() {
+            let qualifying = new_transfers
+                .get(&subscription.token)
+                .into_iter()
+                .flatten()
+                .filter(|t| t.amount >= subscription.threshold);
+
+            match subscription.mode {
+                DeliveryMode::Instant => {
+                    for transfer in qualifying {
+                        messages.push((
+                            subscription.chat,
+                            format!("🐋 Whale Alert!\n\n{}", format_transfer(&subscription.token, transfer)),
+                        ));
+                    }
+                }
+                DeliveryMode::Digest => {
+                    subscription.pending.extend(qualifying.cloned());
+                    if flush_digests && !subscription.pending.is_empty() {
+                        let mut message = format!(
+                            "🐋 Whale Digest: {} large {} transfers\n\n",
+                            subscription.pending.len(),
+                            subscription.token
+                        );
+                        for transfer in subscription.pending.drain(..) {
+                            message.push_str(&format_transfer(&subscription.token, &transfer));
+                            message.push('\n');
+                        }
+                        messages.push((subscription.chat, message));
+                    }
+                }
+            }
+        }
+        messages
+    }
+}
+
+// Watches token ledgers and pushes large transfers into subscribed chats
+pub struct WhaleMonitor {
+    book: JsonStore<WhaleBook>,
+    notifier: Arc<ChatNotifier>,
+}
+
+impl WhaleMonitor {
+    pub fn new(notifier: Arc<ChatNotifier>, data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            book: JsonStore::open(data_dir, "whale_subscriptions.json")?,
+            notifier,
+        })
+    }
+
+    // Subscribing again for the same token replaces the previous threshold and mode
+    pub fn subscribe(&self, chat: Chat, token: String, threshold: f64, mode: DeliveryMode) -> Result<(), String> {
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err("Threshold must be a positive amount".into());
+        }
+        self.book.update(|book| {
+            book.subscriptions.retain(|s| s.chat != chat || !s.token.eq_ignore_ascii_case(&token));
+            book.subscriptions.push(WhaleSubscription {
+                chat,
+                token,
+                threshold,
+                mode,
+                pending: Vec::new(),
+            });
+        })
+    }
+
+    // Remove one token's subscription, or all of them when no token is given
+    pub fn unsubscribe(&self, chat: &Chat, token: Option<&str>) -> Result<usize, String> {
+        self.book.update(|book| {
+            let before = book.subscriptions.len();
+            book.subscriptions.retain(|s| {
+                s.chat != *chat || token.is_some_and(|token| !s.token.eq_ignore_ascii_case(token))
+            });
+            before - book.subscriptions.len()
+        })
+    }
+
+    pub fn subscriptions(&self, chat: &Chat) -> Vec<WhaleSubscription> {
+        self.book.read(|book| book.subscriptions.iter().filter(|s| s.chat == *chat).cloned().collect())
+    }
+
+    pub async fn start_monitoring(self: Arc<Self>, poll_interval: Duration, digest_interval: Duration) {
+        tokio::spawn(async move {
+            let mut last_digest = Instant::now();
+            loop {
+                tokio::time::sleep(poll_interval).await;
+                let flush_digests = last_digest.elapsed() >= digest_interval;
+                if flush_digests {
+                    last_digest = Instant::now();
+                }
+                self.poll(flush_digests).await;
+            }
+        });
+    }
+
+    async fn poll(&self, flush_digests: bool) {
+        // Work out which ledgers anyone is subscribed to
+        let (watched, last_seen) = self.book.read(|book| {
+            let mut watched: Vec<String> = book.subscriptions.iter().map(|s| s.token.clone()).collect();
+            watched.sort();
+            watched.dedup();
+            (watched, book.last_seen.clone())
+        });
+
+        let mut new_transfers: HashMap<String, Vec<LedgerTransfer>> = HashMap::new();
+        let mut newest: HashMap<String, u64> = HashMap::new();
+        for symbol in watched {
+            let Some(token) = tokens::find(&symbol) else {
+                continue;
+            };
+            match last_seen.get(&symbol) {
+                // The first poll for a ledger only records where we are,
+                // so subscribing doesn't replay old history into the chat
+                None => match ledger::latest_index(token).await {
+                    Ok(Some(index)) => {
+                        newest.insert(symbol, index);
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to fetch {} transfers: {}", symbol, e),
+                },
+                Some(seen) => match ledger::fetch_transfers_after(token, *seen, PAGE_SIZE, MAX_PAGES).await {
+                    Ok(scan) => {
+                        if !scan.complete {
+                            warn!("{} ledger is more than {} transactions ahead, skipping older transfers", symbol, PAGE_SIZE * MAX_PAGES);
+                        }
+                        if let Some(index) = scan.newest {
+                            newest.insert(symbol.clone(), index);
+                        }
+                        new_transfers.insert(symbol, scan.transfers);
+                    }
+                    Err(e) => error!("Failed to fetch {} transfers: {}", symbol, e),
+                },
+            }
+        }
+
+        let result = self.book.update(|book| book.apply_scan(&new_transfers, newest, flush_digests));
+
+        match result {
+            Ok(messages) => {
+                for (chat, message) in messages {
+                    self.notifier.send_text(&chat, message);
+                }
+            }
+            Err(e) => error!("Failed to record whale scan: {}", e),
+        }
+    }
+}
+
+fn format_transfer(symbol: &str, transfer: &LedgerTransfer) -> String {
+    format!(
+        "• {:.2} {} from {} to {} (block {}, <t:{}:R>)",
+        transfer.amount,
+        symbol,
+        short_account(&transfer.from),
+        short_account(&transfer.to),
+        transfer.index,
+        transfer.timestamp
+    )
+}
+
+fn short_account(account: &str) -> String {
+    if account.len() <= 12 {
+        return account.to_string();
+    }
+    format!("{}…{}", &account[..6], &account[account.len() - 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One chat subscribed to ICP transfers
+    fn book(threshold: f64, mode: DeliveryMode) -> WhaleBook {
+        let chat: Chat = serde_json::from_str(r#"{"Group":"rrkah-fqaaa-aaaaa-aaaaq-cai"}"#).unwrap();
+        WhaleBook {
+            subscriptions: vec![WhaleSubscription { chat, token: "ICP".to_string(), threshold, mode, pending: Vec::new() }],
+            last_seen: HashMap::new(),
+        }
+    }
+
+    fn transfers(amounts: &[f64]) -> HashMap<String, Vec<LedgerTransfer>> {
+        let transfers = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, amount)| LedgerTransfer {
+                index: i as u64 + 1,
+                from: "from".to_string(),
+                to: "to".to_string(),
+                amount: *amount,
+                timestamp: 0,
+            })
+            .collect();
+        HashMap::from([("ICP".to_string(), transfers)])
+    }
+
+    #[test]
+    fn instant_subscriptions_get_each_transfer_over_the_threshold() {
+        let mut book = book(1000.0, DeliveryMode::Instant);
+        let messages = book.apply_scan(&transfers(&[5000.0, 10.0, 1000.0]), HashMap::new(), false);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].1.contains("5000.00 ICP"));
+    }
+
+    #[test]
+    fn digests_collect_transfers_until_flushed() {
+        let mut book = book(1000.0, DeliveryMode::Digest);
+        assert!(book.apply_scan(&transfers(&[5000.0]), HashMap::new(), false).is_empty());
+        assert!(book.apply_scan(&transfers(&[2000.0, 1.0]), HashMap::new(), false).is_empty());
+
+        let messages = book.apply_scan(&HashMap::new(), HashMap::new(), true);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].1.starts_with("🐋 Whale Digest: 2 large ICP transfers"));
+        assert!(book.subscriptions[0].pending.is_empty());
+        // Nothing pending, nothing posted
+        assert!(book.apply_scan(&HashMap::new(), HashMap::new(), true).is_empty());
+    }
+
+    #[test]
+    fn last_seen_only_moves_forward_for_watched_ledgers() {
+        let mut book = book(1.0, DeliveryMode::Instant);
+        book.apply_scan(&HashMap::new(), HashMap::from([("ICP".to_string(), 50), ("CHAT".to_string(), 7)]), false);
+        book.apply_scan(&HashMap::new(), HashMap::from([("ICP".to_string(), 40)]), false);
+        assert_eq!(book.last_seen, HashMap::from([("ICP".to_string(), 50)]));
+    }
+}
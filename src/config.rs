@@ -1,4 +1,8 @@
+use crate::dex_source::Dex;
+use crate::tokens;
+use oc_bots_sdk::types::Chat;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use tracing::Level;
 use std::env;
@@ -12,6 +16,69 @@ pub struct Config {
     pub port: u16,
     #[serde(with = "LevelDef")]
     pub log_level: Level,
+    #[serde(default = "default_whale_poll_secs")]
+    pub whale_poll_secs: u64,
+    #[serde(default = "default_whale_digest_mins")]
+    pub whale_digest_mins: u64,
+    #[serde(default = "default_dexes")]
+    pub dexes: Vec<Dex>,
+    #[serde(default = "default_arb_pairs")]
+    pub arb_pairs: Vec<ArbPair>,
+    #[serde(default = "default_arb_min_spread")]
+    pub arb_min_spread: f64,
+    #[serde(default = "default_arb_poll_secs")]
+    pub arb_poll_secs: u64,
+    #[serde(default = "default_wallet_tokens")]
+    pub wallet_tokens: Vec<String>,
+    #[serde(default = "default_min_withdrawal_fees")]
+    pub min_withdrawal_fees: u32,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    #[serde(default = "default_escrow_expiry_days")]
+    pub escrow_expiry_days: u64,
+    // Keyed by token symbol, tokens without an entry are unlimited
+    #[serde(default)]
+    pub tip_limits: HashMap<String, TipLimit>,
+    #[serde(default = "default_reconcile_mins")]
+    pub reconcile_mins: u64,
+    // Chat that receives reconciliation alerts, the bot needs an API key for it
+    #[serde(default)]
+    pub admin_chat: Option<Chat>,
+    #[serde(default = "default_wallet_tokens")]
+    pub portfolio_tokens: Vec<String>,
+    #[serde(default = "default_price_poll_secs")]
+    pub price_poll_secs: u64,
+    #[serde(default = "default_price_history_days")]
+    pub price_history_days: u64,
+    #[serde(default = "default_peg_tokens")]
+    pub peg_tokens: Vec<String>,
+    #[serde(default = "default_peg_threshold")]
+    pub peg_threshold: f64,
+    #[serde(default = "default_peg_poll_secs")]
+    pub peg_poll_secs: u64,
+    #[serde(default = "default_digest_symbols")]
+    pub digest_symbols: Vec<String>,
+    #[serde(default = "default_storage_index_canister")]
+    pub storage_index_canister: String,
+}
+
+// A trading pair scanned for arbitrage, `size` is the trade size in base tokens
+#[derive(Deserialize, Debug, Clone)]
+pub struct ArbPair {
+    pub base: String,
+    pub quote: String,
+    pub size: f64,
+}
+
+// Tipping limits for one token, amounts are in whole tokens
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TipLimit {
+    // Largest single tip, for /rain this applies to each member's share
+    pub max_tip: Option<f64>,
+    // Most a user can tip in a UTC day
+    pub daily_cap: Option<f64>,
+    // Tips above this need to be sent again with `confirm` set
+    pub confirm_above: Option<f64>,
 }
 
 fn default_port() -> u16 {
@@ -21,6 +88,89 @@ fn default_port() -> u16 {
         .unwrap_or(13457)
 }
 
+fn default_whale_poll_secs() -> u64 {
+    30
+}
+
+fn default_whale_digest_mins() -> u64 {
+    60
+}
+
+fn default_dexes() -> Vec<Dex> {
+    vec![Dex::KongSwap, Dex::IcpSwap]
+}
+
+fn default_arb_pairs() -> Vec<ArbPair> {
+    vec![
+        ArbPair { base: "ICP".to_string(), quote: "ckUSDC".to_string(), size: 100.0 },
+        ArbPair { base: "ckBTC".to_string(), quote: "ckUSDC".to_string(), size: 0.01 },
+        ArbPair { base: "ckETH".to_string(), quote: "ckUSDC".to_string(), size: 0.25 },
+        ArbPair { base: "CHAT".to_string(), quote: "ICP".to_string(), size: 1000.0 },
+    ]
+}
+
+// Minimum spread in percent, after fees, before an opportunity is reported
+fn default_arb_min_spread() -> f64 {
+    1.0
+}
+
+fn default_arb_poll_secs() -> u64 {
+    120
+}
+
+fn default_wallet_tokens() -> Vec<String> {
+    tokens::TOKENS.iter().map(|t| t.symbol.to_string()).collect()
+}
+
+// Smallest withdrawal, as a multiple of the token's ledger fee
+fn default_min_withdrawal_fees() -> u32 {
+    10
+}
+
+fn default_data_dir() -> String {
+    "./data".to_string()
+}
+
+// Days an unclaimed tip stays in escrow before it is returned to the sender
+fn default_escrow_expiry_days() -> u64 {
+    7
+}
+
+fn default_reconcile_mins() -> u64 {
+    60
+}
+
+fn default_price_poll_secs() -> u64 {
+    300
+}
+
+// How long recorded price samples are kept
+fn default_price_history_days() -> u64 {
+    30
+}
+
+fn default_peg_tokens() -> Vec<String> {
+    vec!["ckUSDC".to_string(), "ckUSDT".to_string()]
+}
+
+// Percent deviation from $1 before a stablecoin is reported as off its peg
+fn default_peg_threshold() -> f64 {
+    0.5
+}
+
+fn default_peg_poll_secs() -> u64 {
+    300
+}
+
+fn default_digest_symbols() -> Vec<String> {
+    ["BTC", "ETH", "ICP", "CHAT"].iter().map(|s| s.to_string()).collect()
+}
+
+// OpenChat's storage index, which allocates buckets for files the bot sends
+fn default_storage_index_canister() -> String {
+    "rturd-qaaaa-aaaaf-aabaq-cai".to_string()
+}
+
 #[derive(Deserialize)]
 #[serde(remote = "Level")]
 enum LevelDef {
@@ -0,0 +1,348 @@
+use super::{Dex, LpPosition, PoolLiquidity, SwapQuote};
+use crate::ledger::{self, nat_to_u128, Account};
+use crate::tokens::{self, Token, TOKENS};
+use candid::{CandidType, Decode, Encode, Int, Nat, Principal};
+use ic_agent::Agent;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::warn;
+
+const SWAP_FACTORY: &str = "4mmnk-kiaaa-aaaag-qbllq-cai";
+
+// Pools are created per fee tier, 0.3% is where most liquidity sits
+const POOL_FEE: u32 = 3000;
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct PoolToken {
+    address: String,
+    standard: String,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct GetPoolArgs {
+    fee: Nat,
+    token0: PoolToken,
+    token1: PoolToken,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct PoolData {
+    fee: Nat,
+    #[serde(rename = "canisterId")]
+    canister_id: Principal,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum SwapError {
+    CommonError,
+    InternalError(String),
+    UnsupportedToken(String),
+    InsufficientFunds,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum PoolResult {
+    #[serde(rename = "ok")]
+    Ok(PoolData),
+    #[serde(rename = "err")]
+    Err(SwapError),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct SwapArgs {
+    #[serde(rename = "amountIn")]
+    amount_in: String,
+    #[serde(rename = "zeroForOne")]
+    zero_for_one: bool,
+    #[serde(rename = "amountOutMinimum")]
+    amount_out_minimum: String,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum QuoteResult {
+    #[serde(rename = "ok")]
+    Ok(Nat),
+    #[serde(rename = "err")]
+    Err(SwapError),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum CallResult<T> {
+    #[serde(rename = "ok")]
+    Ok(T),
+    #[serde(rename = "err")]
+    Err(SwapError),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct PoolMetadata {
+    #[serde(rename = "sqrtPriceX96")]
+    sqrt_price_x96: Nat,
+    token0: PoolToken,
+    token1: PoolToken,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct UserPosition {
+    #[serde(rename = "tickLower")]
+    tick_lower: Int,
+    #[serde(rename = "tickUpper")]
+    tick_upper: Int,
+    liquidity: Nat,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct PositionIncome {
+    #[serde(rename = "tokensOwed0")]
+    tokens_owed_0: Nat,
+    #[serde(rename = "tokensOwed1")]
+    tokens_owed_1: Nat,
+}
+
+// Query a pool method that answers with an ok/err result
+async fn pool_call<T: CandidType + DeserializeOwned>(
+    agent: &Agent,
+    pool: &Principal,
+    method: &str,
+    arg: Vec<u8>,
+) -> Result<T, String> {
+    let response = agent
+        .query(pool, method)
+        .with_arg(arg)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match candid::decode_one::<CallResult<T>>(&response).map_err(|e| e.to_string())? {
+        CallResult::Ok(value) => Ok(value),
+        CallResult::Err(e) => Err(format!("{} failed: {:?}", method, e)),
+    }
+}
+
+fn pool_token(token: &Token) -> PoolToken {
+    PoolToken {
+        address: token.ledger.to_string(),
+        standard: if token.symbol == "ICP" { "ICP" } else { "ICRC2" }.to_string(),
+    }
+}
+
+// Pools order their tokens by ledger id, the quote direction follows from that
+async fn get_pool(agent: &Agent, a: &Token, b: &Token) -> Result<(PoolData, bool), String> {
+    let a_is_token0 = a.ledger < b.ledger;
+    let (token0, token1) = if a_is_token0 { (a, b) } else { (b, a) };
+
+    let factory = Principal::from_text(SWAP_FACTORY).map_err(|e| e.to_string())?;
+    let arg = Encode!(&GetPoolArgs {
+        fee: Nat::from(POOL_FEE),
+        token0: pool_token(token0),
+        token1: pool_token(token1),
+    })
+    .map_err(|e| e.to_string())?;
+
+    let response = agent
+        .query(&factory, "getPool")
+        .with_arg(arg)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match Decode!(&response, PoolResult).map_err(|e| e.to_string())? {
+        PoolResult::Ok(pool) => Ok((pool, a_is_token0)),
+        PoolResult::Err(e) => Err(format!("No {}/{} pool: {:?}", a.symbol, b.symbol, e)),
+    }
+}
+
+async fn pool_quote(agent: &Agent, pool: &PoolData, amount_in: u128, zero_for_one: bool) -> Result<u128, String> {
+    let arg = Encode!(&SwapArgs {
+        amount_in: amount_in.to_string(),
+        zero_for_one,
+        amount_out_minimum: "0".to_string(),
+    })
+    .map_err(|e| e.to_string())?;
+
+    let response = agent
+        .query(&pool.canister_id, "quote")
+        .with_arg(arg)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match Decode!(&response, QuoteResult).map_err(|e| e.to_string())? {
+        QuoteResult::Ok(amount) => nat_to_u128(&amount),
+        QuoteResult::Err(e) => Err(format!("Quote failed: {:?}", e)),
+    }
+}
+
+// Reserves are whatever the pool canister holds on each ledger
+pub async fn pool_liquidity(agent: &Agent, base: &Token, quote: &Token) -> Result<PoolLiquidity, String> {
+    let (pool, zero_for_one) = get_pool(agent, base, quote).await?;
+    let account = Account::new(pool.canister_id);
+    let reserve_base = base.from_units(ledger::balance_of(agent, base, &account).await?);
+    let reserve_quote = quote.from_units(ledger::balance_of(agent, quote, &account).await?);
+
+    // Liquidity is concentrated, so the spot price comes from a tiny quote instead of the reserves
+    let probe_in = base.to_units(0.001).max(1);
+    let probe_out = pool_quote(agent, &pool, probe_in, zero_for_one).await?;
+    let spot = quote.from_units(probe_out) / base.from_units(probe_in);
+
+    Ok(PoolLiquidity {
+        dex: Dex::IcpSwap,
+        reserve_base,
+        reserve_quote,
+        tvl: reserve_base * spot + reserve_quote,
+    })
+}
+
+// Positions are concentrated liquidity NFTs, one set per pool, so every pool
+// between known tokens is checked for positions owned by the principal. A pool that
+// fails to answer is skipped rather than losing the positions in every other pool.
+pub async fn positions(agent: &Agent, owner: Principal) -> Result<Vec<LpPosition>, String> {
+    let mut positions = Vec::new();
+    for (i, a) in TOKENS.iter().enumerate() {
+        for b in &TOKENS[i + 1..] {
+            let Ok((pool, _)) = get_pool(agent, a, b).await else {
+                continue;
+            };
+            match pool_positions(agent, &pool, owner).await {
+                Ok(found) => positions.extend(found),
+                Err(e) => warn!("Skipping ICPSwap {}/{} positions: {}", a.symbol, b.symbol, e),
+            }
+        }
+    }
+    Ok(positions)
+}
+
+async fn pool_positions(agent: &Agent, pool: &PoolData, owner: Principal) -> Result<Vec<LpPosition>, String> {
+    let ids: Vec<Nat> = pool_call(
+        agent,
+        &pool.canister_id,
+        "getUserPositionIdsByPrincipal",
+        Encode!(&owner).map_err(|e| e.to_string())?,
+    )
+    .await?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let metadata: PoolMetadata = pool_call(agent, &pool.canister_id, "metadata", Encode!().map_err(|e| e.to_string())?).await?;
+    let token0 = tokens::find_by_ledger(&metadata.token0.address).ok_or("Unknown pool token")?;
+    let token1 = tokens::find_by_ledger(&metadata.token1.address).ok_or("Unknown pool token")?;
+    let sqrt_price = nat_to_u128(&metadata.sqrt_price_x96)? as f64 / 2f64.powi(96);
+
+    let mut positions = Vec::new();
+    for id in ids {
+        let arg = Encode!(&id).map_err(|e| e.to_string())?;
+        let position: UserPosition = pool_call(agent, &pool.canister_id, "getUserPosition", arg.clone()).await?;
+        let income: PositionIncome = pool_call(agent, &pool.canister_id, "refreshIncome", arg).await?;
+
+        let (amount_0, amount_1) = position_amounts(
+            nat_to_u128(&position.liquidity)? as f64,
+            sqrt_price,
+            int_to_i64(&position.tick_lower)?,
+            int_to_i64(&position.tick_upper)?,
+        );
+        if amount_0 <= 0.0 && amount_1 <= 0.0 {
+            continue;
+        }
+
+        positions.push(LpPosition {
+            dex: Dex::IcpSwap,
+            id: format!("{}/{} #{}", token0.symbol, token1.symbol, id),
+            symbol_0: token0.symbol.to_string(),
+            amount_0: token0.from_units(amount_0 as u128),
+            symbol_1: token1.symbol.to_string(),
+            amount_1: token1.from_units(amount_1 as u128),
+            fees: Some((
+                token0.from_units(nat_to_u128(&income.tokens_owed_0)?),
+                token1.from_units(nat_to_u128(&income.tokens_owed_1)?),
+            )),
+            fees_compounded: false,
+            usd_0: None,
+            usd_1: None,
+        });
+    }
+    Ok(positions)
+}
+
+// Token amounts, in base units, held by a position with liquidity between two ticks
+fn position_amounts(liquidity: f64, sqrt_price: f64, tick_lower: i64, tick_upper: i64) -> (f64, f64) {
+    let sqrt_lower = 1.0001f64.powf(tick_lower as f64 / 2.0);
+    let sqrt_upper = 1.0001f64.powf(tick_upper as f64 / 2.0);
+    if sqrt_price <= sqrt_lower {
+        (liquidity * (sqrt_upper - sqrt_lower) / (sqrt_lower * sqrt_upper), 0.0)
+    } else if sqrt_price < sqrt_upper {
+        (
+            liquidity * (sqrt_upper - sqrt_price) / (sqrt_price * sqrt_upper),
+            liquidity * (sqrt_price - sqrt_lower),
+        )
+    } else {
+        (0.0, liquidity * (sqrt_upper - sqrt_lower))
+    }
+}
+
+fn int_to_i64(value: &Int) -> Result<i64, String> {
+    i64::try_from(&value.0).map_err(|_| "Tick out of range".to_string())
+}
+
+pub async fn quote(agent: &Agent, pay: &Token, amount: f64, receive: &Token) -> Result<SwapQuote, String> {
+    let (pool, zero_for_one) = get_pool(agent, pay, receive).await?;
+
+    let amount_in = pay.to_units(amount);
+    let amount_out = pool_quote(agent, &pool, amount_in, zero_for_one).await?;
+
+    // Price impact is measured against the rate for a trade 1000x smaller
+    let reference_in = (amount_in / 1000).max(1);
+    let reference_out = pool_quote(agent, &pool, reference_in, zero_for_one).await?;
+    let price_impact = if reference_out > 0 {
+        let reference_rate = reference_out as f64 / reference_in as f64;
+        let rate = amount_out as f64 / amount_in as f64;
+        ((1.0 - rate / reference_rate) * 100.0).max(0.0)
+    } else {
+        0.0
+    };
+
+    // Fee tiers are expressed in hundredths of a basis point
+    let fee = nat_to_u128(&pool.fee)? as f64 / 10_000.0;
+
+    Ok(SwapQuote {
+        dex: Dex::IcpSwap,
+        amount_out: receive.from_units(amount_out),
+        price_impact,
+        fee,
+        route: format!("{}/{} {}%", pay.symbol, receive.symbol, fee),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn in_range_positions_hold_both_tokens() {
+        // Price 1 between ticks ±100: the same liquidity on each side
+        let (amount_0, amount_1) = position_amounts(1_000_000.0, 1.0, -100, 100);
+        let sqrt_upper = 1.0001f64.powf(50.0);
+        assert!(close(amount_0, 1_000_000.0 * (sqrt_upper - 1.0) / sqrt_upper));
+        assert!(close(amount_1, 1_000_000.0 * (1.0 - 1.0 / sqrt_upper)));
+        assert!(close(amount_0, amount_1));
+    }
+
+    #[test]
+    fn positions_below_the_range_are_all_token0() {
+        let (amount_0, amount_1) = position_amounts(1_000_000.0, 0.5, -100, 100);
+        assert!(amount_0 > 0.0);
+        assert_eq!(amount_1, 0.0);
+    }
+
+    #[test]
+    fn positions_above_the_range_are_all_token1() {
+        let (amount_0, amount_1) = position_amounts(1_000_000.0, 2.0, -100, 100);
+        assert_eq!(amount_0, 0.0);
+        let (sqrt_lower, sqrt_upper) = (1.0001f64.powf(-50.0), 1.0001f64.powf(50.0));
+        assert!(close(amount_1, 1_000_000.0 * (sqrt_upper - sqrt_lower)));
+    }
+}
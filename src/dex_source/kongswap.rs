@@ -0,0 +1,243 @@
+use super::{Dex, LpPosition, PoolLiquidity, SwapQuote};
+use crate::ledger::nat_to_u128;
+use crate::tokens::Token;
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use ic_agent::Agent;
+use serde::Deserialize;
+
+const KONG_BACKEND: &str = "2ipq2-uqaaa-aaaar-qailq-cai";
+
+// Only the fields the bot uses, candid skips the rest when decoding
+#[derive(CandidType, Deserialize, Debug)]
+struct SwapAmountsReply {
+    receive_amount: Nat,
+    slippage: f64,
+    txs: Vec<SwapAmountsTxReply>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct SwapAmountsTxReply {
+    pool_symbol: String,
+    receive_amount: Nat,
+    lp_fee: Nat,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum SwapAmountsResult {
+    Ok(SwapAmountsReply),
+    Err(String),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct PoolReply {
+    symbol_0: String,
+    address_0: String,
+    balance_0: Nat,
+    // LP fees the pool has collected and not yet paid out, owed to LPs pro rata
+    lp_fee_0: Nat,
+    symbol_1: String,
+    address_1: String,
+    balance_1: Nat,
+    lp_fee_1: Nat,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum PoolsResult {
+    Ok(Vec<PoolReply>),
+    Err(String),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct LpReply {
+    symbol: String,
+    symbol_0: String,
+    amount_0: f64,
+    usd_amount_0: f64,
+    symbol_1: String,
+    amount_1: f64,
+    usd_amount_1: f64,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum UserBalancesReply {
+    LP(LpReply),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum UserBalancesResult {
+    Ok(Vec<UserBalancesReply>),
+    Err(String),
+}
+
+// Kong identifies tokens by chain and ledger canister
+fn token_id(token: &Token) -> String {
+    format!("IC.{}", token.ledger)
+}
+
+async fn pools(agent: &Agent) -> Result<Vec<PoolReply>, String> {
+    let canister_id = Principal::from_text(KONG_BACKEND).map_err(|e| e.to_string())?;
+    let response = agent
+        .query(&canister_id, "pools")
+        .with_arg(Encode!(&None::<String>).map_err(|e| e.to_string())?)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match Decode!(&response, PoolsResult).map_err(|e| e.to_string())? {
+        PoolsResult::Ok(pools) => Ok(pools),
+        PoolsResult::Err(e) => Err(e),
+    }
+}
+
+// Kong pools are constant product, so the spot price follows from the reserves
+pub async fn pool_liquidity(agent: &Agent, base: &Token, quote: &Token) -> Result<PoolLiquidity, String> {
+    let pools = pools(agent).await?;
+
+    let (balance_base, balance_quote) = pools
+        .iter()
+        .find_map(|p| {
+            if p.address_0 == base.ledger && p.address_1 == quote.ledger {
+                Some((&p.balance_0, &p.balance_1))
+            } else if p.address_0 == quote.ledger && p.address_1 == base.ledger {
+                Some((&p.balance_1, &p.balance_0))
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| format!("No {}/{} pool", base.symbol, quote.symbol))?;
+
+    let reserve_quote = quote.from_units(nat_to_u128(balance_quote)?);
+    Ok(PoolLiquidity {
+        dex: Dex::KongSwap,
+        reserve_base: base.from_units(nat_to_u128(balance_base)?),
+        reserve_quote,
+        tvl: reserve_quote * 2.0,
+    })
+}
+
+// Kong reports LP balances with fees already compounded into them. The fee part is the
+// position's share of the fees its pool is holding for LPs.
+pub async fn positions(agent: &Agent, owner: Principal) -> Result<Vec<LpPosition>, String> {
+    let canister_id = Principal::from_text(KONG_BACKEND).map_err(|e| e.to_string())?;
+    let response = agent
+        .query(&canister_id, "user_balances")
+        .with_arg(Encode!(&owner.to_text()).map_err(|e| e.to_string())?)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let balances = match Decode!(&response, UserBalancesResult).map_err(|e| e.to_string())? {
+        UserBalancesResult::Ok(balances) => balances,
+        // Principals that never used Kong come back as an error rather than an empty list
+        UserBalancesResult::Err(e) if e.to_lowercase().contains("not found") => return Ok(Vec::new()),
+        UserBalancesResult::Err(e) => return Err(e),
+    };
+
+    // Without the pools the positions are still worth showing, just without fees
+    let pools = pools(agent).await.unwrap_or_default();
+    let unit_price = |usd: f64, amount: f64| if amount > 0.0 { Some(usd / amount) } else { None };
+    Ok(balances
+        .into_iter()
+        .map(|UserBalancesReply::LP(lp)| LpPosition {
+            dex: Dex::KongSwap,
+            fees: pools
+                .iter()
+                .find(|p| p.symbol_0 == lp.symbol_0 && p.symbol_1 == lp.symbol_1)
+                .and_then(|pool| compounded_fees(pool, lp.amount_0, lp.amount_1).ok()),
+            fees_compounded: true,
+            id: lp.symbol,
+            usd_0: unit_price(lp.usd_amount_0, lp.amount_0),
+            usd_1: unit_price(lp.usd_amount_1, lp.amount_1),
+            symbol_0: lp.symbol_0,
+            amount_0: lp.amount_0,
+            symbol_1: lp.symbol_1,
+            amount_1: lp.amount_1,
+        })
+        .filter(|p| p.amount_0 > 0.0 || p.amount_1 > 0.0)
+        .collect())
+}
+
+// Kong sizes a position as its share of the pool's balance plus LP fees, so the fee part
+// is the same fraction of the position's amounts
+fn compounded_fees(pool: &PoolReply, amount_0: f64, amount_1: f64) -> Result<(f64, f64), String> {
+    let fee_share = |balance: &Nat, lp_fee: &Nat| -> Result<f64, String> {
+        let (balance, lp_fee) = (nat_to_u128(balance)? as f64, nat_to_u128(lp_fee)? as f64);
+        Ok(if balance + lp_fee > 0.0 { lp_fee / (balance + lp_fee) } else { 0.0 })
+    };
+    Ok((
+        amount_0 * fee_share(&pool.balance_0, &pool.lp_fee_0)?,
+        amount_1 * fee_share(&pool.balance_1, &pool.lp_fee_1)?,
+    ))
+}
+
+pub async fn quote(agent: &Agent, pay: &Token, amount: f64, receive: &Token) -> Result<SwapQuote, String> {
+    let canister_id = Principal::from_text(KONG_BACKEND).map_err(|e| e.to_string())?;
+    let arg = Encode!(&token_id(pay), &Nat::from(pay.to_units(amount)), &token_id(receive))
+        .map_err(|e| e.to_string())?;
+
+    let response = agent
+        .query(&canister_id, "swap_amounts")
+        .with_arg(arg)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let reply = match Decode!(&response, SwapAmountsResult).map_err(|e| e.to_string())? {
+        SwapAmountsResult::Ok(reply) => reply,
+        SwapAmountsResult::Err(e) => return Err(e),
+    };
+
+    // Each hop charges its LP fee in the token it pays out
+    let mut fee = 0.0;
+    for tx in &reply.txs {
+        let received = nat_to_u128(&tx.receive_amount)? as f64;
+        let lp_fee = nat_to_u128(&tx.lp_fee)? as f64;
+        if received + lp_fee > 0.0 {
+            fee += lp_fee / (received + lp_fee) * 100.0;
+        }
+    }
+
+    Ok(SwapQuote {
+        dex: Dex::KongSwap,
+        amount_out: receive.from_units(nat_to_u128(&reply.receive_amount)?),
+        price_impact: reply.slippage,
+        fee,
+        route: reply
+            .txs
+            .iter()
+            .map(|tx| tx.pool_symbol.clone())
+            .collect::<Vec<_>>()
+            .join(" → "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(balance_0: u64, lp_fee_0: u64, balance_1: u64, lp_fee_1: u64) -> PoolReply {
+        PoolReply {
+            symbol_0: "ICP".to_string(),
+            address_0: String::new(),
+            balance_0: Nat::from(balance_0),
+            lp_fee_0: Nat::from(lp_fee_0),
+            symbol_1: "ckUSDT".to_string(),
+            address_1: String::new(),
+            balance_1: Nat::from(balance_1),
+            lp_fee_1: Nat::from(lp_fee_1),
+        }
+    }
+
+    #[test]
+    fn fees_are_the_lp_fee_share_of_the_position() {
+        // A tenth of each side of the pool is LP fees
+        let (fee_0, fee_1) = compounded_fees(&pool(900, 100, 1800, 200), 50.0, 20.0).unwrap();
+        assert!((fee_0 - 5.0).abs() < 1e-12);
+        assert!((fee_1 - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn empty_pools_have_no_fees() {
+        assert_eq!(compounded_fees(&pool(0, 0, 0, 0), 1.0, 1.0).unwrap(), (0.0, 0.0));
+    }
+}
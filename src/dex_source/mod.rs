@@ -0,0 +1,197 @@
+use crate::ledger;
+use crate::tokens::{self, Token};
+use candid::Principal;
+use ic_agent::Agent;
+use serde::Deserialize;
+use tracing::warn;
+
+mod icpswap;
+mod kongswap;
+
+// DEXes on the Internet Computer the bot can query
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dex {
+    KongSwap,
+    IcpSwap,
+}
+
+impl Dex {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dex::KongSwap => "KongSwap",
+            Dex::IcpSwap => "ICPSwap",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SwapQuote {
+    pub dex: Dex,
+    pub amount_out: f64,
+    // Percentages
+    pub price_impact: f64,
+    pub fee: f64,
+    pub route: String,
+}
+
+// Prices are in quote tokens per base token
+#[derive(Debug, Clone)]
+pub struct PoolPrice {
+    pub dex: Dex,
+    pub buy: f64,
+    pub sell: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LpPosition {
+    pub dex: Dex,
+    pub id: String,
+    pub symbol_0: String,
+    pub amount_0: f64,
+    pub symbol_1: String,
+    pub amount_1: f64,
+    // Fees earned in each token, when the DEX reports them
+    pub fees: Option<(f64, f64)>,
+    // Whether those fees are already in the amounts rather than waiting to be collected
+    pub fees_compounded: bool,
+    // USD per token, filled in from the DEX or from pool prices
+    pub usd_0: Option<f64>,
+    pub usd_1: Option<f64>,
+}
+
+impl LpPosition {
+    pub fn value(&self) -> Option<f64> {
+        Some(self.amount_0 * self.usd_0? + self.amount_1 * self.usd_1?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolLiquidity {
+    pub dex: Dex,
+    pub reserve_base: f64,
+    pub reserve_quote: f64,
+    // Total value locked, in quote tokens
+    pub tvl: f64,
+}
+
+// Reads prices and quotes from DEX canisters using query calls only
+pub struct DexSource {
+    agent: Agent,
+    dexes: Vec<Dex>,
+}
+
+impl DexSource {
+    pub fn new(agent: Agent, dexes: Vec<Dex>) -> Self {
+        Self { agent, dexes }
+    }
+
+    // Ask every configured DEX for a quote, keeping failures so they can be reported
+    pub async fn quote(&self, pay: &Token, amount: f64, receive: &Token) -> Vec<(Dex, Result<SwapQuote, String>)> {
+        let mut quotes = Vec::new();
+        for dex in &self.dexes {
+            quotes.push((*dex, self.quote_on(*dex, pay, amount, receive).await));
+        }
+        quotes
+    }
+
+    // Effective buy and sell prices of `size` base tokens on every configured DEX.
+    // Both come from real quotes so they are net of pool fees and price impact.
+    pub async fn pool_prices(&self, base: &Token, quote: &Token, size: f64) -> Vec<PoolPrice> {
+        let mut prices = Vec::new();
+        for dex in &self.dexes {
+            let sell = match self.quote_on(*dex, base, size, quote).await {
+                Ok(sell) if sell.amount_out > 0.0 => sell,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("{} has no {}/{} price: {}", dex.name(), base.symbol, quote.symbol, e);
+                    continue;
+                }
+            };
+            let buy = match self.quote_on(*dex, quote, sell.amount_out, base).await {
+                Ok(buy) if buy.amount_out > 0.0 => buy,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("{} has no {}/{} price: {}", dex.name(), quote.symbol, base.symbol, e);
+                    continue;
+                }
+            };
+            prices.push(PoolPrice {
+                dex: *dex,
+                sell: sell.amount_out / size,
+                buy: sell.amount_out / buy.amount_out,
+            });
+        }
+        prices
+    }
+
+    // Ledger fee for moving a token in or out of a DEX, in whole tokens
+    pub async fn transfer_fee(&self, token: &Token) -> Result<f64, String> {
+        Ok(token.from_units(ledger::fee(&self.agent, token).await?))
+    }
+
+    // Reserves and TVL of the base/quote pool on every configured DEX that has one
+    pub async fn pool_liquidity(&self, base: &Token, quote: &Token) -> Vec<PoolLiquidity> {
+        let mut pools = Vec::new();
+        for dex in &self.dexes {
+            let liquidity = match dex {
+                Dex::KongSwap => kongswap::pool_liquidity(&self.agent, base, quote).await,
+                Dex::IcpSwap => icpswap::pool_liquidity(&self.agent, base, quote).await,
+            };
+            match liquidity {
+                Ok(liquidity) => pools.push(liquidity),
+                Err(e) => warn!("{} has no {}/{} pool: {}", dex.name(), base.symbol, quote.symbol, e),
+            }
+        }
+        pools
+    }
+
+    // Liquidity positions an account holds on every configured DEX
+    pub async fn positions(&self, owner: Principal) -> Vec<(Dex, Result<Vec<LpPosition>, String>)> {
+        let mut results = Vec::new();
+        for dex in &self.dexes {
+            let positions = match dex {
+                Dex::KongSwap => kongswap::positions(&self.agent, owner).await,
+                Dex::IcpSwap => icpswap::positions(&self.agent, owner).await,
+            };
+            let positions = match positions {
+                Ok(mut positions) => {
+                    for position in positions.iter_mut() {
+                        if position.usd_0.is_none() {
+                            position.usd_0 = self.usd_price(&position.symbol_0).await;
+                        }
+                        if position.usd_1.is_none() {
+                            position.usd_1 = self.usd_price(&position.symbol_1).await;
+                        }
+                    }
+                    Ok(positions)
+                }
+                Err(e) => Err(e),
+            };
+            results.push((*dex, positions));
+        }
+        results
+    }
+
+    // USD price of a token, taking ckUSDC as the dollar
+    pub async fn usd_price(&self, symbol: &str) -> Option<f64> {
+        let token = tokens::find(symbol)?;
+        if token.symbol == "ckUSDC" || token.symbol == "ckUSDT" {
+            return Some(1.0);
+        }
+        let usd = tokens::find("ckUSDC")?;
+        for dex in &self.dexes {
+            if let Ok(quote) = self.quote_on(*dex, token, 1.0, usd).await {
+                return Some(quote.amount_out);
+            }
+        }
+        None
+    }
+
+    async fn quote_on(&self, dex: Dex, pay: &Token, amount: f64, receive: &Token) -> Result<SwapQuote, String> {
+        match dex {
+            Dex::KongSwap => kongswap::quote(&self.agent, pay, amount, receive).await,
+            Dex::IcpSwap => icpswap::quote(&self.agent, pay, amount, receive).await,
+        }
+    }
+}
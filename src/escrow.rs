@@ -0,0 +1,398 @@
+use crate::audit::Holder;
+use crate::ledger::TransferReceipt;
+use crate::notifier::ChatNotifier;
+use crate::storage::JsonStore;
+use crate::tip_log::{TipLog, TipRecord};
+use crate::tokens::{self, Token};
+use crate::wallet::{self, Wallet};
+use oc_bots_sdk::types::{Chat, UserId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+// Retrying a payout with the same memo is only safe while the ledger's 24 hour
+// deduplication window still covers the first attempt
+const RELEASE_RETRY_SECS: i64 = 23 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EscrowStatus {
+    Pending,
+    // Being paid out to the recipient or back to the sender, until the ledger confirms it
+    Claiming,
+    Refunding,
+    Claimed,
+    Refunded,
+    // Couldn't be paid out and needs an admin to look at it
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EscrowEntry {
+    pub id: u64,
+    pub sender: UserId,
+    pub recipient: UserId,
+    pub token: String,
+    // In base units, as transferred into escrow
+    pub amount: u128,
+    // Ledger block of the transfer into escrow
+    pub block_index: u64,
+    pub chat: Option<Chat>,
+    pub created: i64,
+    pub expires: i64,
+    pub status: EscrowStatus,
+    // When the payout was first attempted
+    #[serde(default)]
+    pub released: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct EscrowBook {
+    next_id: u64,
+    // Users who have used their wallet and can receive tips directly
+    registered: HashSet<UserId>,
+    entries: Vec<EscrowEntry>,
+}
+
+impl EscrowBook {
+    // Start paying out the user's pending tips, along with any claims still in flight
+    fn start_claims(&mut self, user: UserId, now: i64) -> Vec<EscrowEntry> {
+        self.registered.insert(user);
+        let mut claims = Vec::new();
+        for entry in self.entries.iter_mut().filter(|e| e.recipient == user) {
+            if entry.status == EscrowStatus::Pending {
+                entry.status = EscrowStatus::Claiming;
+                entry.released = Some(now);
+            }
+            if entry.status == EscrowStatus::Claiming {
+                claims.push(entry.clone());
+            }
+        }
+        claims
+    }
+
+    // Start refunding expired tips, returning them along with every payout still in flight
+    fn start_refunds(&mut self, now: i64) -> Vec<EscrowEntry> {
+        let mut releases = Vec::new();
+        for entry in self.entries.iter_mut() {
+            if entry.status == EscrowStatus::Pending && entry.expires <= now {
+                entry.status = EscrowStatus::Refunding;
+                entry.released = Some(now);
+            }
+            if matches!(entry.status, EscrowStatus::Claiming | EscrowStatus::Refunding) {
+                releases.push(entry.clone());
+            }
+        }
+        releases
+    }
+
+    fn set_status(&mut self, id: u64, status: EscrowStatus) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            entry.status = status;
+        }
+    }
+}
+
+pub enum TipOutcome {
+    Sent(TransferReceipt),
+    Escrowed(TransferReceipt, EscrowEntry),
+}
+
+// Routes tips either straight to the recipient or into escrow until they claim them
+pub struct Escrow {
+    wallet: Arc<Wallet>,
+    notifier: Arc<ChatNotifier>,
+    tip_log: Arc<TipLog>,
+    store: JsonStore<EscrowBook>,
+    expiry: Duration,
+}
+
+impl Escrow {
+    pub fn new(
+        wallet: Arc<Wallet>,
+        notifier: Arc<ChatNotifier>,
+        tip_log: Arc<TipLog>,
+        data_dir: &str,
+        expiry: Duration,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            wallet,
+            notifier,
+            tip_log,
+            store: JsonStore::open(data_dir, "escrow.json")?,
+            expiry,
+        })
+    }
+
+    pub async fn tip(
+        &self,
+        sender: UserId,
+        recipient: UserId,
+        token: &Token,
+        amount: u128,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TipOutcome, String> {
+        let now = chrono::Utc::now().timestamp();
+        let registered = self.store.read(|book| book.registered.contains(&recipient));
+        // Whoever claims an escrowed tip pays the fee out of it, so it has to cover that
+        if !registered && amount <= self.wallet.fee(token).await? {
+            return Err(format!("Tips to members without a wallet must be more than the {} ledger fee", token.symbol));
+        }
+        let mut record = TipRecord {
+            sender,
+            recipient,
+            token: token.symbol.to_string(),
+            amount,
+            chat,
+            block_index: 0,
+            escrowed: false,
+            timestamp: now,
+            refunded: false,
+        };
+
+        if registered {
+            let receipt = self.wallet.tip(sender, recipient, token, amount, chat, memo).await?;
+            if !receipt.duplicate {
+                record.block_index = receipt.block_index;
+                self.tip_log.record(record);
+            }
+            return Ok(TipOutcome::Sent(receipt));
+        }
+
+        let receipt = self.wallet.to_escrow(sender, token, amount, chat, memo).await?;
+        if receipt.duplicate {
+            // A retried command, the escrow entry was created the first time round
+            let existing = self.store.read(|book| {
+                book.entries
+                    .iter()
+                    .find(|e| e.token == token.symbol && e.block_index == receipt.block_index)
+                    .cloned()
+            });
+            if let Some(entry) = existing {
+                return Ok(TipOutcome::Escrowed(receipt, entry));
+            }
+        }
+        record.block_index = receipt.block_index;
+        record.escrowed = true;
+        self.tip_log.record(record);
+        let entry = self.store.update(|book| {
+            book.next_id += 1;
+            let entry = EscrowEntry {
+                id: book.next_id,
+                sender,
+                recipient,
+                token: token.symbol.to_string(),
+                amount,
+                block_index: receipt.block_index,
+                chat,
+                created: now,
+                expires: now + self.expiry.as_secs() as i64,
+                status: EscrowStatus::Pending,
+                released: None,
+            };
+            book.entries.push(entry.clone());
+            entry
+        })?;
+        Ok(TipOutcome::Escrowed(receipt, entry))
+    }
+
+    // Outcome of a tip an earlier attempt at the same command already sent
+    pub fn completed(&self, token: &Token, memo: &[u8]) -> Option<TipOutcome> {
+        let (to, receipt) = self.wallet.completed_transfer(memo)?;
+        if to != Holder::Escrow {
+            return Some(TipOutcome::Sent(receipt));
+        }
+        let entry = self.store.read(|book| {
+            book.entries
+                .iter()
+                .find(|e| e.token == token.symbol && e.block_index == receipt.block_index)
+                .cloned()
+        })?;
+        Some(TipOutcome::Escrowed(receipt, entry))
+    }
+
+    // Mark the user's wallet as set up and release anything waiting for them
+    pub async fn register(&self, user: UserId) -> Result<Vec<(EscrowEntry, Result<TransferReceipt, String>)>, String> {
+        let now = chrono::Utc::now().timestamp();
+        self.wallet.track(user)?;
+        let claims = self.store.update(|book| book.start_claims(user, now))?;
+
+        let mut results = Vec::new();
+        for entry in claims {
+            let result = self.release(&entry).await;
+            results.push((entry, result));
+        }
+        Ok(results)
+    }
+
+    pub async fn start_expiry(self: Arc<Self>, check_interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(check_interval).await;
+                self.refund_expired().await;
+            }
+        });
+    }
+
+    // Refund expired tips and retry payouts that haven't been confirmed yet
+    async fn refund_expired(&self) {
+        let now = chrono::Utc::now().timestamp();
+        let releases = match self.store.update(|book| book.start_refunds(now)) {
+            Ok(releases) => releases,
+            Err(e) => {
+                error!("Failed to update escrow: {}", e);
+                return;
+            }
+        };
+
+        for entry in releases {
+            let Ok(receipt) = self.release(&entry).await else {
+                continue;
+            };
+            if entry.status != EscrowStatus::Refunding {
+                continue;
+            }
+            self.tip_log.mark_refunded(&entry.token, entry.block_index);
+            if let (Some(chat), Some(token)) = (&entry.chat, tokens::find(&entry.token)) {
+                self.notifier.send_text(
+                    chat,
+                    format!(
+                        "↩️ The {} {} tip from @UserId({}) to @UserId({}) wasn't claimed in time and was returned (block {})",
+                        token.from_units(entry.amount),
+                        token.symbol,
+                        entry.sender,
+                        entry.recipient,
+                        receipt.block_index
+                    ),
+                );
+            }
+        }
+    }
+
+    // Pay out an escrowed tip that is Claiming or Refunding. The entry keeps that status until
+    // the ledger confirms the transfer, and every attempt reuses the escrow's memo and
+    // created_at_time, so retrying one that errored but landed comes back as a duplicate.
+    async fn release(&self, entry: &EscrowEntry) -> Result<TransferReceipt, String> {
+        let (to, settled) = match entry.status {
+            EscrowStatus::Claiming => (entry.recipient, EscrowStatus::Claimed),
+            EscrowStatus::Refunding => (entry.sender, EscrowStatus::Refunded),
+            _ => return Err("Escrowed tip isn't being paid out".to_string()),
+        };
+        let Some(token) = tokens::find(&entry.token) else {
+            self.set_status(entry.id, EscrowStatus::Failed);
+            return Err("Unsupported token".to_string());
+        };
+        // Tips escrowed before the fee was checked up front can never be paid out
+        if entry.amount <= self.wallet.fee(token).await? {
+            error!("Escrow {} doesn't cover the ledger fee, marking it failed", entry.id);
+            self.set_status(entry.id, EscrowStatus::Failed);
+            return Err("Escrowed amount doesn't cover the ledger fee".to_string());
+        }
+
+        let memo = wallet::escrow_memo(entry.id);
+        let result = self.wallet.from_escrow(to, token, entry.amount, entry.chat, memo).await;
+        match &result {
+            Ok(_) => self.set_status(entry.id, settled),
+            Err(e) => {
+                error!("Failed to release escrow {} as {:?}: {}", entry.id, settled, e);
+                let first_attempt = entry.released.unwrap_or(entry.created);
+                if chrono::Utc::now().timestamp() - first_attempt > RELEASE_RETRY_SECS {
+                    error!("Escrow {} wasn't paid out within the ledger's deduplication window, marking it failed", entry.id);
+                    self.set_status(entry.id, EscrowStatus::Failed);
+                }
+            }
+        }
+        result
+    }
+
+    fn set_status(&self, id: u64, status: EscrowStatus) {
+        if let Err(e) = self.store.update(|book| book.set_status(id, status)) {
+            error!("Failed to update escrow: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn user(id: u8) -> UserId {
+        UserId::from(Principal::from_slice(&[id]))
+    }
+
+    fn entry(id: u64, recipient: UserId, expires: i64) -> EscrowEntry {
+        EscrowEntry {
+            id,
+            sender: user(1),
+            recipient,
+            token: "ICP".to_string(),
+            amount: 100_000,
+            block_index: id,
+            chat: None,
+            created: 0,
+            expires,
+            status: EscrowStatus::Pending,
+            released: None,
+        }
+    }
+
+    fn book(entries: Vec<EscrowEntry>) -> EscrowBook {
+        EscrowBook {
+            next_id: entries.len() as u64,
+            registered: HashSet::new(),
+            entries,
+        }
+    }
+
+    #[test]
+    fn claims_stay_in_flight_until_confirmed() {
+        let mut book = book(vec![entry(1, user(2), 100), entry(2, user(3), 100)]);
+        let claims = book.start_claims(user(2), 10);
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].status, EscrowStatus::Claiming);
+        assert_eq!(claims[0].released, Some(10));
+        assert!(book.registered.contains(&user(2)));
+
+        // A failed payout is retried as the same claim, not started over
+        let retried = book.start_claims(user(2), 20);
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].released, Some(10));
+
+        book.set_status(1, EscrowStatus::Claimed);
+        assert!(book.start_claims(user(2), 30).is_empty());
+    }
+
+    #[test]
+    fn expired_tips_are_refunded() {
+        let mut book = book(vec![entry(1, user(2), 100), entry(2, user(3), 200)]);
+        let refunds = book.start_refunds(150);
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].id, 1);
+        assert_eq!(refunds[0].status, EscrowStatus::Refunding);
+        assert_eq!(book.entries[1].status, EscrowStatus::Pending);
+
+        // The recipient turning up after the refund started doesn't get paid as well
+        assert!(book.start_claims(user(2), 160).is_empty());
+    }
+
+    #[test]
+    fn claims_in_flight_are_retried_and_never_refunded() {
+        let mut book = book(vec![entry(1, user(2), 100)]);
+        book.start_claims(user(2), 50);
+        let releases = book.start_refunds(150);
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].status, EscrowStatus::Claiming);
+    }
+
+    #[test]
+    fn settled_and_failed_entries_are_left_alone() {
+        let mut book = book(vec![entry(1, user(2), 100), entry(2, user(2), 100)]);
+        book.set_status(1, EscrowStatus::Refunded);
+        book.set_status(2, EscrowStatus::Failed);
+        assert!(book.start_refunds(150).is_empty());
+        assert!(book.start_claims(user(2), 150).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::portfolio::{Trade, TradeSide};
+use crate::tip_log::TipRecord;
+use crate::tokens;
+use chrono::{DateTime, Utc};
+use oc_bots_sdk::types::UserId;
+
+const HEADER: &str = "date,type,asset,amount,price_usd,counterparty,block_index";
+
+// One CSV of manual trades and tips, sorted by date, for tax reporting
+pub fn transactions_csv(user: UserId, trades: &[Trade], tips: &[TipRecord]) -> String {
+    let mut rows: Vec<(i64, String)> = Vec::new();
+    for trade in trades {
+        let kind = match trade.side {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+        };
+        rows.push((
+            trade.timestamp,
+            format!("{},{},{},{},{},,", date(trade.timestamp), kind, trade.symbol, trade.amount, trade.price),
+        ));
+    }
+    for tip in tips {
+        let (kind, counterparty) = if tip.sender == user {
+            ("tip_sent", tip.recipient)
+        } else {
+            ("tip_received", tip.sender)
+        };
+        let amount = match tokens::find(&tip.token) {
+            Some(token) => token.from_units(tip.amount).to_string(),
+            None => tip.amount.to_string(),
+        };
+        rows.push((
+            tip.timestamp,
+            format!(
+                "{},{},{},{},,{},{}",
+                date(tip.timestamp),
+                kind,
+                tip.token,
+                amount,
+                counterparty,
+                tip.block_index
+            ),
+        ));
+    }
+    rows.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut csv = HEADER.to_string();
+    for (_, row) in rows {
+        csv.push('\n');
+        csv.push_str(&row);
+    }
+    csv
+}
+
+// File name of an export covering the given days, e.g. portfolio-2024-01-01-2024-12-31.csv
+pub fn file_name(from: i64, to: i64) -> String {
+    let day = |timestamp: i64| {
+        DateTime::<Utc>::from_timestamp(timestamp, 0)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+    format!("portfolio-{}-{}.csv", day(from), day(to))
+}
+
+fn date(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(|d| d.to_rfc3339())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    #[test]
+    fn file_name_covers_both_days() {
+        assert_eq!(file_name(1_704_067_200, 1_735_603_200), "portfolio-2024-01-01-2024-12-31.csv");
+    }
+
+    #[test]
+    fn rows_are_sorted_by_date() {
+        let trade = |side, timestamp| Trade {
+            side,
+            symbol: "ICP".to_string(),
+            amount: 2.5,
+            price: 10.0,
+            timestamp,
+        };
+        let user = UserId::from(Principal::anonymous());
+        let csv = transactions_csv(user, &[trade(TradeSide::Sell, 200), trade(TradeSide::Buy, 100)], &[]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(lines[1], "1970-01-01T00:01:40+00:00,buy,ICP,2.5,10,,");
+        assert_eq!(lines[2], "1970-01-01T00:03:20+00:00,sell,ICP,2.5,10,,");
+    }
+}
@@ -0,0 +1,169 @@
+// Technical indicators over closing prices, oldest first
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cross {
+    // Fast average moved above the slow one
+    Golden,
+    // Fast average moved below the slow one
+    Death,
+}
+
+pub struct Macd {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+}
+
+pub struct Bollinger {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+// Simple moving average of each full window
+pub fn sma(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 {
+        return Vec::new();
+    }
+    values
+        .windows(period)
+        .map(|window| window.iter().sum::<f64>() / period as f64)
+        .collect()
+}
+
+// Exponential moving average, seeded with the SMA of the first window
+pub fn ema(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || values.len() < period {
+        return Vec::new();
+    }
+    let k = 2.0 / (period as f64 + 1.0);
+    let mut current = values[..period].iter().sum::<f64>() / period as f64;
+    let mut result = vec![current];
+    for value in &values[period..] {
+        current = value * k + current * (1.0 - k);
+        result.push(current);
+    }
+    result
+}
+
+// Wilder's relative strength index at the latest value
+pub fn rsi(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() <= period {
+        return None;
+    }
+    let changes: Vec<f64> = values.windows(2).map(|w| w[1] - w[0]).collect();
+    let mut gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / period as f64;
+    let mut loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / period as f64;
+    for change in &changes[period..] {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+    if gain == 0.0 && loss == 0.0 {
+        return Some(50.0);
+    }
+    if loss == 0.0 {
+        return Some(100.0);
+    }
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
+
+// MACD line (fast EMA minus slow EMA), its signal EMA and their difference at the latest value
+pub fn macd(values: &[f64], fast: usize, slow: usize, signal: usize) -> Option<Macd> {
+    let (fast_ema, slow_ema) = (ema(values, fast), ema(values, slow));
+    // The slow EMA starts later, so line the two up on their latest values
+    let offset = fast_ema.len().checked_sub(slow_ema.len())?;
+    let line: Vec<f64> = slow_ema.iter().zip(&fast_ema[offset..]).map(|(s, f)| f - s).collect();
+    let macd = *line.last()?;
+    let signal = *ema(&line, signal).last()?;
+    Some(Macd { macd, signal, histogram: macd - signal })
+}
+
+// Bollinger bands `width` standard deviations around the SMA of the last `period` values
+pub fn bollinger(values: &[f64], period: usize, width: f64) -> Option<Bollinger> {
+    let window = values.get(values.len().checked_sub(period)?..).filter(|w| !w.is_empty())?;
+    let middle = window.iter().sum::<f64>() / period as f64;
+    let variance = window.iter().map(|v| (v - middle).powi(2)).sum::<f64>() / period as f64;
+    let deviation = variance.sqrt() * width;
+    Some(Bollinger { upper: middle + deviation, middle, lower: middle - deviation })
+}
+
+// Whether the fast SMA crossed the slow one between the last two values
+pub fn sma_cross(values: &[f64], fast: usize, slow: usize) -> Option<Cross> {
+    let (fast, slow) = (sma(values, fast), sma(values, slow));
+    let [.., fast_prev, fast_now] = fast[..] else {
+        return None;
+    };
+    let [.., slow_prev, slow_now] = slow[..] else {
+        return None;
+    };
+    if fast_prev <= slow_prev && fast_now > slow_now {
+        Some(Cross::Golden)
+    } else if fast_prev >= slow_prev && fast_now < slow_now {
+        Some(Cross::Death)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sma_averages_each_full_window() {
+        assert_eq!(sma(&[1.0, 2.0, 3.0, 4.0], 2), vec![1.5, 2.5, 3.5]);
+        assert!(sma(&[1.0, 2.0], 3).is_empty());
+        assert!(sma(&[1.0, 2.0], 0).is_empty());
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_first_sma() {
+        // k = 2 / (3 + 1) = 0.5
+        let result = ema(&[2.0, 4.0, 6.0, 8.0, 12.0], 3);
+        assert_eq!(result, vec![4.0, 6.0, 9.0]);
+        assert!(ema(&[1.0], 2).is_empty());
+    }
+
+    #[test]
+    fn rsi_uses_wilder_smoothing() {
+        // Gains and losses start at 0.5, then 0.75/0.25 and 0.375/0.625, so RS = 0.6
+        assert!(close(rsi(&[1.0, 2.0, 1.0, 2.0, 1.0], 2).unwrap(), 37.5));
+        assert_eq!(rsi(&[1.0, 2.0, 3.0, 4.0], 2), Some(100.0));
+        assert_eq!(rsi(&[5.0, 5.0, 5.0], 2), Some(50.0));
+        assert_eq!(rsi(&[1.0, 2.0], 2), None);
+    }
+
+    #[test]
+    fn sma_cross_detects_the_latest_crossing() {
+        // The 2-period average falls below the 3-period one on the last value
+        assert_eq!(sma_cross(&[1.0, 2.0, 3.0, 4.0, 1.0], 2, 3), Some(Cross::Death));
+        assert_eq!(sma_cross(&[4.0, 3.0, 2.0, 1.0, 4.0], 2, 3), Some(Cross::Golden));
+        assert_eq!(sma_cross(&[1.0, 2.0, 3.0, 4.0, 5.0], 2, 3), None);
+        assert_eq!(sma_cross(&[1.0, 2.0, 3.0], 2, 3), None);
+    }
+
+    #[test]
+    fn macd_lines_up_the_fast_and_slow_emas() {
+        // A steady rise keeps the fast EMA a constant distance above the slow one
+        let values: Vec<f64> = (1..=10).map(|v| v as f64).collect();
+        let result = macd(&values, 2, 4, 3).unwrap();
+        assert!(close(result.macd, 1.0));
+        assert!(close(result.signal, 1.0));
+        assert!(close(result.histogram, 0.0));
+        assert!(macd(&values[..5], 2, 4, 3).is_none());
+    }
+
+    #[test]
+    fn bollinger_bands_use_the_population_deviation() {
+        let bands = bollinger(&[100.0, 2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 8, 2.0).unwrap();
+        assert!(close(bands.middle, 5.0));
+        assert!(close(bands.upper, 9.0));
+        assert!(close(bands.lower, 1.0));
+        assert!(bollinger(&[1.0], 2, 2.0).is_none());
+        assert!(bollinger(&[1.0], 0, 2.0).is_none());
+    }
+}
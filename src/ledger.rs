@@ -0,0 +1,478 @@
+use crate::tokens::Token;
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use ic_agent::Agent;
+use reqwest;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+// ICP ledger transactions are served by the dashboard ledger API, ICRC ledgers by the ICRC API
+const ICP_LEDGER_API: &str = "https://ledger-api.internetcomputer.org";
+const ICRC_LEDGER_API: &str = "https://icrc-api.internetcomputer.org/api/v1";
+
+// ICRC-1 account
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+impl Account {
+    pub fn new(owner: Principal) -> Self {
+        Self { owner, subaccount: None }
+    }
+
+    fn non_default_subaccount(&self) -> Option<&[u8]> {
+        self.subaccount.as_deref().filter(|s| s.iter().any(|b| *b != 0))
+    }
+}
+
+// ICRC-1 textual encoding: `owner` or `owner-checksum.subaccount`
+impl std::fmt::Display for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.non_default_subaccount() {
+            None => write!(f, "{}", self.owner),
+            Some(subaccount) => {
+                let hex: String = subaccount.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(
+                    f,
+                    "{}-{}.{}",
+                    self.owner,
+                    account_checksum(&self.owner, subaccount),
+                    hex.trim_start_matches('0')
+                )
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Account {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let Some((owner_and_checksum, hex)) = text.trim().split_once('.') else {
+            let owner = Principal::from_text(text.trim()).map_err(|_| "Invalid account")?;
+            return Ok(Account::new(owner));
+        };
+        let (owner, checksum) = owner_and_checksum
+            .rsplit_once('-')
+            .ok_or("Invalid account")?;
+        let owner = Principal::from_text(owner).map_err(|_| "Invalid account owner")?;
+
+        if hex.is_empty() || hex.len() > 64 || hex.starts_with('0') {
+            return Err("Invalid account subaccount".to_string());
+        }
+        let padded = format!("{:0>64}", hex);
+        let subaccount = (0..32)
+            .map(|i| u8::from_str_radix(&padded[i * 2..i * 2 + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| "Invalid account subaccount")?;
+
+        if account_checksum(&owner, &subaccount) != checksum {
+            return Err("Invalid account checksum".to_string());
+        }
+        Ok(Account {
+            owner,
+            subaccount: Some(subaccount),
+        })
+    }
+}
+
+// CRC-32 of owner and subaccount bytes, base32 encoded without padding
+fn account_checksum(owner: &Principal, subaccount: &[u8]) -> String {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in owner.as_slice().iter().chain(subaccount) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    let bytes = (!crc).to_be_bytes();
+
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let bits = u64::from(u32::from_be_bytes(bytes)) << 8;
+    (0..7)
+        .map(|i| ALPHABET[((bits >> (35 - i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum TransferResult {
+    Ok(Nat),
+    Err(TransferError),
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferReceipt {
+    pub block_index: u64,
+    // In base units
+    pub fee: u128,
+    // The ledger already had this transfer, `block_index` is the original
+    pub duplicate: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerTransfer {
+    pub index: u64,
+    pub from: String,
+    pub to: String,
+    pub amount: f64,
+    pub timestamp: i64,
+}
+
+// One page of a ledger's transactions, newest first. `newest` and `oldest` cover every
+// transaction on the page, including the non-transfers that were left out.
+struct TransferPage {
+    transfers: Vec<LedgerTransfer>,
+    newest: Option<u64>,
+    oldest: Option<u64>,
+}
+
+// Transfers found on a ledger since a given block, oldest first
+pub struct TransferScan {
+    pub transfers: Vec<LedgerTransfer>,
+    // Newest block on the ledger, transfer or not
+    pub newest: Option<u64>,
+    // False when the page limit ran out before reaching the starting block
+    pub complete: bool,
+}
+
+// Index of the newest transaction on a token's ledger
+pub async fn latest_index(token: &Token) -> Result<Option<u64>, String> {
+    Ok(fetch_transfer_page(token, 1, 0).await?.newest)
+}
+
+// Every transfer after block `after`. Pages back through the newest transactions until it
+// reaches `after`, so a busy ledger doesn't lose transfers between polls.
+pub async fn fetch_transfers_after(
+    token: &Token,
+    after: u64,
+    page_size: u32,
+    max_pages: u32,
+) -> Result<TransferScan, String> {
+    scan_pages(after, page_size, max_pages, |limit, offset| fetch_transfer_page(token, limit, offset)).await
+}
+
+async fn scan_pages<F, Fut>(after: u64, page_size: u32, max_pages: u32, mut fetch: F) -> Result<TransferScan, String>
+where
+    F: FnMut(u32, u32) -> Fut,
+    Fut: Future<Output = Result<TransferPage, String>>,
+{
+    let mut transfers = Vec::new();
+    let mut newest = None;
+    let mut complete = false;
+    for page in 0..max_pages {
+        let page = fetch(page_size, page * page_size).await?;
+        newest = newest.max(page.newest);
+        transfers.extend(page.transfers.into_iter().filter(|t| t.index > after));
+        if page.oldest.is_none_or(|oldest| oldest <= after + 1) {
+            complete = true;
+            break;
+        }
+    }
+    // New transactions arriving between pages shift the offsets, which can repeat a few
+    transfers.sort_by_key(|t| t.index);
+    transfers.dedup_by_key(|t| t.index);
+    Ok(TransferScan { transfers, newest, complete })
+}
+
+async fn fetch_transfer_page(token: &Token, limit: u32, offset: u32) -> Result<TransferPage, String> {
+    let (url, list_key, index_key) = if token.symbol == "ICP" {
+        (
+            format!("{}/transactions?limit={}&offset={}&sort_by=-block_height", ICP_LEDGER_API, limit, offset),
+            "blocks",
+            "block_height",
+        )
+    } else {
+        (
+            format!("{}/ledgers/{}/transactions?limit={}&offset={}&sort_by=-index", ICRC_LEDGER_API, token.ledger, limit, offset),
+            "data",
+            "index",
+        )
+    };
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let data: serde_json::Value = response.json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let entries = data[list_key]
+        .as_array()
+        .ok_or_else(|| "Failed to parse ledger transactions".to_string())?;
+
+    let indexes = entries.iter().filter_map(|entry| number(&entry[index_key]).map(|i| i as u64));
+    let newest = indexes.clone().max();
+    let oldest = indexes.min();
+
+    let transfers = entries
+        .iter()
+        .filter_map(|entry| {
+            if token.symbol == "ICP" {
+                Some(LedgerTransfer {
+                    index: number(&entry["block_height"])? as u64,
+                    from: entry["from_account_identifier"].as_str().unwrap_or_default().to_string(),
+                    to: entry["to_account_identifier"].as_str().unwrap_or_default().to_string(),
+                    amount: token.from_units(number(&entry["amount"])?),
+                    timestamp: timestamp(&entry["created_at"]),
+                })
+            } else {
+                if entry["kind"].as_str() != Some("transfer") {
+                    return None;
+                }
+                Some(LedgerTransfer {
+                    index: number(&entry["index"])? as u64,
+                    from: entry["from_owner"].as_str().unwrap_or_default().to_string(),
+                    to: entry["to_owner"].as_str().unwrap_or_default().to_string(),
+                    amount: token.from_units(number(&entry["amount"])?),
+                    timestamp: timestamp(&entry["timestamp"]),
+                })
+            }
+        })
+        .collect();
+
+    Ok(TransferPage { transfers, newest, oldest })
+}
+
+// Balance of an account in the token's base units
+pub async fn balance_of(agent: &Agent, token: &Token, account: &Account) -> Result<u128, String> {
+    let ledger = Principal::from_text(token.ledger).map_err(|e| e.to_string())?;
+    let response = agent
+        .query(&ledger, "icrc1_balance_of")
+        .with_arg(Encode!(account).map_err(|e| e.to_string())?)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+    nat_to_u128(&Decode!(&response, Nat).map_err(|e| e.to_string())?)
+}
+
+pub fn nat_to_u128(value: &Nat) -> Result<u128, String> {
+    u128::try_from(&value.0).map_err(|_| "Amount out of range".to_string())
+}
+
+// Transfer fee of a token in base units
+pub async fn fee(agent: &Agent, token: &Token) -> Result<u128, String> {
+    let ledger = Principal::from_text(token.ledger).map_err(|e| e.to_string())?;
+    let response = agent
+        .query(&ledger, "icrc1_fee")
+        .with_arg(Encode!().map_err(|e| e.to_string())?)
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+    nat_to_u128(&Decode!(&response, Nat).map_err(|e| e.to_string())?)
+}
+
+// ICRC-1 transfer of `amount` base units out of one of the bot's subaccounts.
+// The fee is paid on top of the amount by the sending subaccount.
+// With a memo and created_at_time set, the ledger rejects a repeat of the same
+// transfer inside its deduplication window as Duplicate, which counts as success
+#[allow(clippy::too_many_arguments)]
+pub async fn transfer(
+    agent: &Agent,
+    token: &Token,
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: u128,
+    fee: u128,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+) -> Result<TransferReceipt, String> {
+    let ledger = Principal::from_text(token.ledger).map_err(|e| e.to_string())?;
+    let arg = TransferArg {
+        from_subaccount,
+        to,
+        amount: Nat::from(amount),
+        fee: Some(Nat::from(fee)),
+        memo,
+        created_at_time,
+    };
+
+    let response = agent
+        .update(&ledger, "icrc1_transfer")
+        .with_arg(Encode!(&arg).map_err(|e| e.to_string())?)
+        .call_and_wait()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match Decode!(&response, TransferResult).map_err(|e| e.to_string())? {
+        TransferResult::Ok(block_index) => Ok(TransferReceipt {
+            block_index: nat_to_u128(&block_index)? as u64,
+            fee,
+            duplicate: false,
+        }),
+        TransferResult::Err(TransferError::Duplicate { duplicate_of }) => Ok(TransferReceipt {
+            block_index: nat_to_u128(&duplicate_of)? as u64,
+            fee,
+            duplicate: true,
+        }),
+        TransferResult::Err(e) => Err(describe_transfer_error(token, &e)),
+    }
+}
+
+fn describe_transfer_error(token: &Token, error: &TransferError) -> String {
+    match error {
+        TransferError::InsufficientFunds { balance } => format!(
+            "Insufficient funds, balance is {} {}",
+            nat_to_u128(balance).map(|b| token.from_units(b)).unwrap_or_default(),
+            token.symbol
+        ),
+        TransferError::BadFee { expected_fee } => format!(
+            "Ledger expected a fee of {} {}",
+            nat_to_u128(expected_fee).map(|f| token.from_units(f)).unwrap_or_default(),
+            token.symbol
+        ),
+        TransferError::TemporarilyUnavailable => "Ledger is temporarily unavailable, try again".to_string(),
+        TransferError::GenericError { message, .. } => format!("Ledger error: {}", message),
+        other => format!("Transfer failed: {:?}", other),
+    }
+}
+
+// The ledger APIs return large integers either as JSON numbers or as strings
+fn number(value: &serde_json::Value) -> Option<u128> {
+    match value {
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Number(n) => n.as_u64().map(u128::from),
+        _ => None,
+    }
+}
+
+// Timestamps come back as RFC 3339 strings, unix seconds or unix nanoseconds
+fn timestamp(value: &serde_json::Value) -> i64 {
+    if let Some(date) = value.as_str().and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok()) {
+        return date.timestamp();
+    }
+    match number(value) {
+        Some(n) if n > 10_000_000_000 => (n / 1_000_000_000) as i64,
+        Some(n) => n as i64,
+        None => chrono::Utc::now().timestamp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the ICRC-1 textual encoding spec
+    const OWNER: &str = "k2t6j-2nvnp-4zjm3-25dtz-6xhaa-c7boj-5gayf-oj3xs-i43lp-teztq-6ae";
+
+    #[test]
+    fn accounts_round_trip_the_spec_examples() {
+        let full = format!("{}-dfxgiyy.102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20", OWNER);
+        let account: Account = full.parse().unwrap();
+        assert_eq!(account.subaccount.as_deref(), Some(&(1..=32).collect::<Vec<u8>>()[..]));
+        assert_eq!(account.to_string(), full);
+
+        let short = format!("{}-6cc627i.1", OWNER);
+        let account: Account = short.parse().unwrap();
+        assert_eq!(account.subaccount.as_ref().unwrap()[31], 1);
+        assert_eq!(account.to_string(), short);
+    }
+
+    #[test]
+    fn default_subaccounts_print_as_the_owner() {
+        let owner = Principal::from_text(OWNER).unwrap();
+        let account = Account { owner, subaccount: Some(vec![0; 32]) };
+        assert_eq!(account.to_string(), OWNER);
+        assert!(OWNER.parse::<Account>().unwrap().subaccount.is_none());
+    }
+
+    #[test]
+    fn bad_checksums_and_subaccounts_are_rejected() {
+        assert_eq!(
+            format!("{}-6cc627j.1", OWNER).parse::<Account>().unwrap_err(),
+            "Invalid account checksum"
+        );
+        // Leading zeros must be dropped from the subaccount
+        assert!(format!("{}-6cc627i.01", OWNER).parse::<Account>().is_err());
+        assert!(format!("{}-6cc627i.", OWNER).parse::<Account>().is_err());
+        assert!(format!("{}.1", OWNER).parse::<Account>().is_err());
+    }
+
+    fn transfer(index: u64) -> LedgerTransfer {
+        LedgerTransfer { index, from: "a".to_string(), to: "b".to_string(), amount: 1.0, timestamp: 0 }
+    }
+
+    // A ledger of blocks 1..=newest served newest first, like the index APIs
+    fn ledger_page(newest: u64, limit: u32, offset: u32) -> TransferPage {
+        let top = newest.saturating_sub(offset as u64);
+        let bottom = top.saturating_sub(limit as u64 - 1).max(1);
+        let indexes: Vec<u64> = (bottom..=top).rev().collect();
+        TransferPage {
+            transfers: indexes.iter().map(|i| transfer(*i)).collect(),
+            newest: indexes.first().copied(),
+            oldest: indexes.last().copied(),
+        }
+    }
+
+    #[tokio::test]
+    async fn scans_page_back_to_the_last_seen_block() {
+        let mut offsets = Vec::new();
+        let scan = scan_pages(5, 4, 10, |limit, offset| {
+            offsets.push(offset);
+            async move { Ok(ledger_page(15, limit, offset)) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(offsets, vec![0, 4, 8]);
+        assert!(scan.complete);
+        assert_eq!(scan.newest, Some(15));
+        assert_eq!(scan.transfers.iter().map(|t| t.index).collect::<Vec<_>>(), (6..=15).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_page_limit() {
+        let scan = scan_pages(5, 4, 2, |limit, offset| async move { Ok(ledger_page(100, limit, offset)) })
+            .await
+            .unwrap();
+        assert!(!scan.complete);
+        assert_eq!(scan.transfers.first().map(|t| t.index), Some(93));
+        assert_eq!(scan.newest, Some(100));
+    }
+
+    #[tokio::test]
+    async fn repeated_transfers_across_pages_are_dropped() {
+        // A new block arriving between pages shifts the second page by one
+        let mut newest = 10;
+        let scan = scan_pages(2, 4, 5, |limit, offset| {
+            let page = ledger_page(newest, limit, offset);
+            newest += 1;
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
+        let indexes: Vec<u64> = scan.transfers.iter().map(|t| t.index).collect();
+        assert_eq!(indexes, (3..=10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn an_empty_ledger_is_complete() {
+        let scan = scan_pages(0, 4, 5, |_, _| async { Ok(TransferPage { transfers: Vec::new(), newest: None, oldest: None }) })
+            .await
+            .unwrap();
+        assert!(scan.complete);
+        assert!(scan.transfers.is_empty());
+    }
+}
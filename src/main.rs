@@ -7,19 +7,58 @@ use axum::{
 };
 use dotenv::dotenv;
 use oc_bots_sdk::api::command::{CommandHandlerRegistry, CommandResponse};
-use oc_bots_sdk::api::definition::BotDefinition;
+use oc_bots_sdk::api::definition::{AutonomousConfig, BotDefinition, BotPermissions, MessagePermission};
 use oc_bots_sdk::oc_api::client::ClientFactory;
 use oc_bots_sdk_offchain::{env, AgentRuntime};
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{info, error};
 use tracing_subscriber::fmt::format::FmtSpan;
 use crate::commands::alert::Alert;
+use crate::commands::alert_monitor::AlertMonitor;
+use crate::commands::arb_monitor::ArbMonitor;
+use crate::commands::digest_scheduler::DigestScheduler;
+use crate::commands::peg_monitor::PegMonitor;
+use crate::commands::price_monitor::PriceMonitor;
+use crate::commands::whale_monitor::WhaleMonitor;
+use crate::audit::AuditLog;
+use crate::blob_storage::BlobStorage;
+use crate::dex_source::DexSource;
+use crate::escrow::Escrow;
+use crate::notifier::ChatNotifier;
+use crate::portfolio::Portfolio;
+use crate::price_history::PriceHistory;
+use crate::reconcile::Reconciler;
+use crate::tip_limits::TipLimits;
+use crate::tip_log::TipLog;
+use crate::wallet::Wallet;
+use crate::watchlist::Watchlists;
 
+mod audit;
+mod blob_storage;
+mod chart;
 mod config;
 mod commands;
+mod dex_source;
+mod escrow;
+mod export;
+mod indicators;
+mod ledger;
+mod notifier;
+mod portfolio;
+mod price_history;
+mod price_source;
+mod reconcile;
+mod storage;
+mod tip_limits;
+mod tip_log;
+mod tokens;
+mod util;
+mod wallet;
+mod watchlist;
 
 // Structure to hold application state
 struct AppState {
@@ -51,16 +90,132 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Build agent for OpenChat communication
     let agent = oc_bots_sdk_offchain::build_agent(config.ic_url.clone(), &config.pem_file).await;
 
+    // DEX canisters are read with query calls through the same agent
+    let dex_source = Arc::new(DexSource::new(agent.clone(), config.dexes.clone()));
+
+    // Every transfer the wallet makes is appended to the audit log
+    let audit = Arc::new(AuditLog::new(&config.data_dir)?);
+
+    // Tracked principals and accounts, valued from their ledger balances
+    let portfolio = Arc::new(Portfolio::new(agent.clone(), &config.portfolio_tokens, &config.data_dir)?);
+
+    // Custodial wallet signing ledger transfers with the bot's identity
+    let wallet = Arc::new(Wallet::new(agent.clone(), config.min_withdrawal_fees, audit.clone(), &config.data_dir)?);
+
+    // Files sent into chats, such as exports and charts, are uploaded to OpenChat's storage buckets
+    let blob_storage = Arc::new(BlobStorage::new(agent.clone(), &config.storage_index_canister)?);
+
     // Create runtime and client factory
     let runtime = AgentRuntime::new(agent, tokio::runtime::Runtime::new()?);
     let client_factory = Arc::new(ClientFactory::new(runtime));
 
+    // Notifier for posting into chats outside of a command
+    let notifier = Arc::new(ChatNotifier::new(client_factory.clone()));
+
+    // Start watching ledgers for large transfers
+    let whale_monitor = Arc::new(WhaleMonitor::new(notifier.clone(), &config.data_dir)?);
+    whale_monitor
+        .clone()
+        .start_monitoring(
+            Duration::from_secs(config.whale_poll_secs),
+            Duration::from_secs(config.whale_digest_mins * 60),
+        )
+        .await;
+
+    // Start scanning DEX prices for arbitrage
+    let arb_monitor = Arc::new(ArbMonitor::new(
+        dex_source.clone(),
+        notifier.clone(),
+        config.arb_pairs.clone(),
+        config.arb_min_spread,
+        &config.data_dir,
+    )?);
+    arb_monitor
+        .clone()
+        .start_monitoring(Duration::from_secs(config.arb_poll_secs))
+        .await;
+
+    // Start tracking stablecoins against their peg
+    let peg_monitor = Arc::new(PegMonitor::new(
+        dex_source.clone(),
+        notifier.clone(),
+        config.peg_tokens.clone(),
+        config.peg_threshold,
+        &config.data_dir,
+    )?);
+    peg_monitor
+        .clone()
+        .start_monitoring(Duration::from_secs(config.peg_poll_secs))
+        .await;
+
+    // Persisted record of every tip, for history and leaderboards
+    let tip_log = Arc::new(TipLog::new(&config.data_dir)?);
+
+    // Tips to users without a wallet wait in escrow until claimed or expired
+    let escrow = Arc::new(Escrow::new(
+        wallet.clone(),
+        notifier.clone(),
+        tip_log.clone(),
+        &config.data_dir,
+        Duration::from_secs(config.escrow_expiry_days * 24 * 60 * 60),
+    )?);
+    escrow.clone().start_expiry(Duration::from_secs(60 * 60)).await;
+
+    // Check custodial balances against the ledgers
+    let reconciler = Arc::new(Reconciler::new(wallet.clone(), audit, notifier.clone(), config.admin_chat));
+    reconciler
+        .start_monitoring(Duration::from_secs(config.reconcile_mins * 60))
+        .await;
+
+    // Per-token tip size, daily cap and confirmation threshold
+    let tip_limits = Arc::new(TipLimits::new(config.tip_limits.clone(), &config.data_dir)?);
+
+    // Record prices for /history
+    let price_history = Arc::new(PriceHistory::new(&config.data_dir, config.price_history_days)?);
+    PriceMonitor::new(price_history.clone())
+        .start_monitoring(Duration::from_secs(config.price_poll_secs))
+        .await;
+
+    // Start checking price, liquidity and indicator alerts
+    let alert_monitor = Arc::new(AlertMonitor::new(
+        dex_source.clone(),
+        notifier.clone(),
+        price_history.clone(),
+        &config.data_dir,
+    )?);
+    alert_monitor.start_monitoring().await;
+
+    // Post scheduled market digests into chats
+    let digest_scheduler = Arc::new(DigestScheduler::new(
+        notifier.clone(),
+        alert_monitor.clone(),
+        config.digest_symbols.clone(),
+        &config.data_dir,
+    )?);
+    digest_scheduler.clone().start().await;
+
+    // Per-user watched symbols
+    let watchlists = Arc::new(Watchlists::new(&config.data_dir)?);
+
     // Create command registry and register commands
     let commands = CommandHandlerRegistry::new(client_factory)
         .register(commands::echo::Echo)
-        .register(commands::price::Price)
-        .register(commands::dex::DexMonitor)
-        .register(Alert::new());
+        .register(commands::price::Price::new(watchlists.clone(), price_history.clone()))
+        .register(commands::dex::DexMonitor::new(whale_monitor, arb_monitor, dex_source, &config.data_dir)?)
+        .register(Alert::new(alert_monitor))
+        .register(commands::tip::Tip::new(escrow.clone(), tip_limits.clone()))
+        .register(commands::rain::Rain::new(wallet.clone(), escrow.clone(), tip_limits, &config.data_dir)?)
+        .register(commands::tips::Tips::new(tip_log.clone()))
+        .register(commands::portfolio::PortfolioCommand::new(portfolio, tip_log, blob_storage.clone()))
+        .register(commands::watch::Watch::new(watchlists, price_history.clone()))
+        .register(commands::history::History::new(price_history.clone()))
+        .register(commands::ta::TechnicalAnalysis::new(price_history.clone()))
+        .register(commands::chart::Chart::new(price_history, blob_storage))
+        .register(commands::convert::Convert)
+        .register(commands::peg::Peg::new(peg_monitor))
+        .register(commands::digest::Digest::new(digest_scheduler))
+        .register(commands::wallet::WalletCommand::new(wallet, escrow, &config.wallet_tokens))
+        .on_sync_api_key(Box::new(move |cxt| notifier.sync_api_key(cxt)));
 
     let app_state = AppState {
         oc_public_key: config.oc_public_key,
@@ -94,7 +249,10 @@ async fn bot_definition(State(state): State<Arc<AppState>>) -> (StatusCode, Head
     let definition = BotDefinition {
         description: "uTip - AI-Powered DEX Trading Assistant & Crypto Tipping Bot".to_string(),
         commands,
-        autonomous_config: None,
+        autonomous_config: Some(AutonomousConfig {
+            permissions: BotPermissions::from_message_permission(MessagePermission::Text),
+            sync_api_key: true,
+        }),
     };
     
     let mut headers = HeaderMap::new();
@@ -0,0 +1,71 @@
+use oc_bots_sdk::api::command::{CommandResponse, SuccessResult};
+use oc_bots_sdk::oc_api::client::ClientFactory;
+use oc_bots_sdk::types::{ActionScope, BotApiKeyContext, BotCommandContext, BotCommandScope, Chat};
+use oc_bots_sdk_offchain::AgentRuntime;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+// Posts messages into chats outside of a command, using the API keys
+// OpenChat syncs to the bot when it is granted autonomous permissions
+pub struct ChatNotifier {
+    client_factory: Arc<ClientFactory<AgentRuntime>>,
+    api_keys: RwLock<HashMap<Chat, BotApiKeyContext>>,
+}
+
+impl ChatNotifier {
+    pub fn new(client_factory: Arc<ClientFactory<AgentRuntime>>) -> Self {
+        Self {
+            client_factory,
+            api_keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Handler for the `sync_api_key` command sent by OpenChat
+    pub fn sync_api_key(&self, context: BotApiKeyContext) -> CommandResponse {
+        match context.scope {
+            ActionScope::Chat(chat) => {
+                info!("Received API key for chat {:?}", chat);
+                self.api_keys.write().unwrap().insert(chat, context);
+            }
+            ActionScope::Community(community_id) => {
+                warn!("Ignoring community API key for {:?}", community_id);
+            }
+        }
+        CommandResponse::Success(SuccessResult { message: None })
+    }
+
+    // Send a text message to a chat, returns false if the bot can't post there yet
+    pub fn send_text(&self, chat: &Chat, text: String) -> bool {
+        let context = match self.api_keys.read().unwrap().get(chat) {
+            Some(context) => context.clone(),
+            None => {
+                warn!("No API key for chat {:?}, dropping message", chat);
+                return false;
+            }
+        };
+
+        self.client_factory
+            .build_api_key_client(context)
+            .send_text_message(text)
+            .with_block_level_markdown(true)
+            .execute_then_return_message(|_, _| ());
+        true
+    }
+}
+
+// The chat a command was issued in, if it wasn't issued at community level
+pub fn command_chat(context: &BotCommandContext) -> Option<Chat> {
+    match &context.scope {
+        BotCommandScope::Chat(details) => Some(details.chat),
+        BotCommandScope::Community(_) => None,
+    }
+}
+
+// Id of the message the bot replies with, OpenChat keeps it when it retries a command
+pub fn command_message_id(context: &BotCommandContext) -> Option<u128> {
+    match &context.scope {
+        BotCommandScope::Chat(details) => Some(details.message_id.into()),
+        BotCommandScope::Community(_) => None,
+    }
+}
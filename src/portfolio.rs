@@ -0,0 +1,302 @@
+use crate::ledger::{self, Account};
+use crate::price_source::{self, PriceQuote};
+use crate::storage::JsonStore;
+use crate::tokens::{self, Token};
+use ic_agent::Agent;
+use oc_bots_sdk::types::UserId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use tracing::warn;
+
+const MAX_ACCOUNTS: usize = 10;
+
+// Amounts below this are treated as zero when matching lots
+const DUST: f64 = 1e-12;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct UserPortfolio {
+    // ICRC-1 account text of every tracked principal or account
+    pub accounts: Vec<String>,
+    // Manually entered buys and sells, oldest first
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    pub side: TradeSide,
+    pub symbol: String,
+    pub amount: f64,
+    // USD per unit
+    pub price: f64,
+    pub timestamp: i64,
+}
+
+// Open lots and realised P&L for one asset, from matching sells against the oldest buys
+pub struct Position {
+    pub symbol: String,
+    pub amount: f64,
+    // What the open lots cost in total
+    pub cost: f64,
+    pub realised: f64,
+    pub price: Option<f64>,
+}
+
+impl Position {
+    pub fn value(&self) -> Option<f64> {
+        self.price.map(|p| p * self.amount)
+    }
+
+    pub fn unrealised(&self) -> Option<f64> {
+        self.value().map(|v| v - self.cost)
+    }
+}
+
+pub struct TokenHolding {
+    pub token: &'static Token,
+    pub amount: f64,
+    pub quote: Option<PriceQuote>,
+}
+
+impl TokenHolding {
+    pub fn value(&self) -> Option<f64> {
+        self.quote.map(|q| q.usd * self.amount)
+    }
+
+    // Value 24 hours ago at today's amount, for the portfolio's overall change
+    pub fn value_24h_ago(&self) -> Option<f64> {
+        let quote = self.quote?;
+        Some(self.amount * quote.usd / (1.0 + quote.change_24h? / 100.0))
+    }
+}
+
+// Per-user list of accounts whose ledger balances make up their portfolio
+pub struct Portfolio {
+    agent: Agent,
+    tokens: Vec<&'static Token>,
+    // Keyed by user id text
+    store: JsonStore<HashMap<String, UserPortfolio>>,
+}
+
+impl Portfolio {
+    pub fn new(agent: Agent, symbols: &[String], data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            agent,
+            tokens: symbols.iter().filter_map(|s| tokens::find(s)).collect(),
+            store: JsonStore::open(data_dir, "portfolios.json")?,
+        })
+    }
+
+    pub fn accounts(&self, user: UserId) -> Vec<String> {
+        self.store.read(|portfolios| {
+            portfolios
+                .get(&user.to_string())
+                .map(|p| p.accounts.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn add_account(&self, user: UserId, account: &Account) -> Result<(), String> {
+        let account = account.to_string();
+        self.store.update(|portfolios| {
+            let portfolio = portfolios.entry(user.to_string()).or_default();
+            if portfolio.accounts.contains(&account) {
+                return Err("That account is already in your portfolio".to_string());
+            }
+            if portfolio.accounts.len() >= MAX_ACCOUNTS {
+                return Err(format!("You can track at most {} accounts", MAX_ACCOUNTS));
+            }
+            portfolio.accounts.push(account);
+            Ok(())
+        })?
+    }
+
+    pub fn remove_account(&self, user: UserId, account: &Account) -> Result<bool, String> {
+        let account = account.to_string();
+        self.store.update(|portfolios| match portfolios.get_mut(&user.to_string()) {
+            Some(portfolio) => {
+                let before = portfolio.accounts.len();
+                portfolio.accounts.retain(|a| *a != account);
+                portfolio.accounts.len() != before
+            }
+            None => false,
+        })
+    }
+
+    pub fn trades(&self, user: UserId) -> Vec<Trade> {
+        self.store.read(|portfolios| {
+            portfolios
+                .get(&user.to_string())
+                .map(|p| p.trades.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    // Sells can't exceed what the earlier buys left open
+    pub fn record_trade(&self, user: UserId, trade: Trade) -> Result<(), String> {
+        self.store.update(|portfolios| {
+            let portfolio = portfolios.entry(user.to_string()).or_default();
+            let mut trades = portfolio.trades.clone();
+            trades.push(trade);
+            fifo(&trades)?;
+            portfolio.trades = trades;
+            Ok(())
+        })?
+    }
+
+    // FIFO positions for the manual trades, valued at current prices
+    pub async fn positions(&self, user: UserId) -> Result<Vec<Position>, String> {
+        let mut positions = fifo(&self.trades(user))?;
+        let symbols: Vec<&str> = positions.iter().map(|p| p.symbol.as_str()).collect();
+        match price_source::usd_quotes(&symbols).await {
+            Ok(quotes) => {
+                for position in &mut positions {
+                    position.price = quotes.get(position.symbol.as_str()).map(|q| q.usd);
+                }
+            }
+            Err(e) => warn!("Failed to price positions: {}", e),
+        }
+        Ok(positions)
+    }
+
+    // Balances summed over the user's accounts, with current prices where available
+    pub async fn holdings(&self, user: UserId) -> Result<Vec<TokenHolding>, String> {
+        let accounts = self
+            .accounts(user)
+            .iter()
+            .map(|a| a.parse::<Account>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut holdings = Vec::new();
+        for token in &self.tokens {
+            let mut total = 0u128;
+            for account in &accounts {
+                match ledger::balance_of(&self.agent, token, account).await {
+                    Ok(balance) => total += balance,
+                    Err(e) => warn!("Failed to read {} balance of {}: {}", token.symbol, account, e),
+                }
+            }
+            if total > 0 {
+                holdings.push(TokenHolding {
+                    token,
+                    amount: token.from_units(total),
+                    quote: None,
+                });
+            }
+        }
+
+        let symbols: Vec<&str> = holdings.iter().map(|h| h.token.symbol).collect();
+        match price_source::usd_quotes(&symbols).await {
+            Ok(quotes) => {
+                for holding in &mut holdings {
+                    holding.quote = quotes.get(holding.token.symbol).copied();
+                }
+            }
+            Err(e) => warn!("Failed to price portfolio: {}", e),
+        }
+        Ok(holdings)
+    }
+}
+
+// Replay trades in order, each sell consuming the oldest open lots first
+fn fifo(trades: &[Trade]) -> Result<Vec<Position>, String> {
+    let mut lots: BTreeMap<&str, (VecDeque<(f64, f64)>, f64)> = BTreeMap::new();
+    for trade in trades {
+        let (open, realised) = lots.entry(trade.symbol.as_str()).or_default();
+        match trade.side {
+            TradeSide::Buy => open.push_back((trade.amount, trade.price)),
+            TradeSide::Sell => {
+                let held: f64 = open.iter().map(|(amount, _)| amount).sum();
+                if trade.amount > held + DUST {
+                    return Err(format!("You only hold {} {}", held, trade.symbol));
+                }
+                let mut remaining = trade.amount;
+                while remaining > DUST {
+                    let Some(lot) = open.front_mut() else {
+                        break;
+                    };
+                    let matched = remaining.min(lot.0);
+                    *realised += matched * (trade.price - lot.1);
+                    lot.0 -= matched;
+                    remaining -= matched;
+                    if lot.0 <= DUST {
+                        open.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(lots
+        .into_iter()
+        .map(|(symbol, (open, realised))| Position {
+            symbol: symbol.to_string(),
+            amount: open.iter().map(|(amount, _)| amount).sum(),
+            cost: open.iter().map(|(amount, price)| amount * price).sum(),
+            realised,
+            price: None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(side: TradeSide, symbol: &str, amount: f64, price: f64) -> Trade {
+        Trade {
+            side,
+            symbol: symbol.to_string(),
+            amount,
+            price,
+            timestamp: 0,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sells_consume_the_oldest_lots_first() {
+        let positions = fifo(&[
+            trade(TradeSide::Buy, "ICP", 10.0, 5.0),
+            trade(TradeSide::Buy, "ICP", 10.0, 8.0),
+            trade(TradeSide::Sell, "ICP", 15.0, 10.0),
+        ])
+        .unwrap();
+        assert_eq!(positions.len(), 1);
+        let icp = &positions[0];
+        // 10 at 5 and 5 at 8 sold at 10
+        assert!(close(icp.realised, 10.0 * 5.0 + 5.0 * 2.0));
+        assert!(close(icp.amount, 5.0));
+        assert!(close(icp.cost, 40.0));
+    }
+
+    #[test]
+    fn symbols_are_tracked_separately() {
+        let positions = fifo(&[
+            trade(TradeSide::Buy, "ICP", 1.0, 5.0),
+            trade(TradeSide::Buy, "BTC", 1.0, 60_000.0),
+            trade(TradeSide::Sell, "BTC", 1.0, 50_000.0),
+        ])
+        .unwrap();
+        let btc = positions.iter().find(|p| p.symbol == "BTC").unwrap();
+        assert!(close(btc.realised, -10_000.0));
+        assert!(close(btc.amount, 0.0));
+        let icp = positions.iter().find(|p| p.symbol == "ICP").unwrap();
+        assert!(close(icp.cost, 5.0));
+    }
+
+    #[test]
+    fn selling_more_than_held_is_rejected() {
+        assert!(fifo(&[trade(TradeSide::Buy, "ICP", 1.0, 5.0), trade(TradeSide::Sell, "ICP", 2.0, 5.0)]).is_err());
+        assert!(fifo(&[trade(TradeSide::Sell, "ICP", 1.0, 5.0)]).is_err());
+    }
+}
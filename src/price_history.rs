@@ -0,0 +1,270 @@
+use crate::chart;
+use crate::price_source;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{error, warn};
+
+#[derive(Serialize, Deserialize)]
+struct Sample {
+    symbol: String,
+    timestamp: i64,
+    price: f64,
+}
+
+// Open, high, low and close over a period, with when the extremes happened
+pub struct Ohlc {
+    pub open: f64,
+    pub high: (i64, f64),
+    pub low: (i64, f64),
+    pub close: f64,
+    pub start: i64,
+    pub end: i64,
+    pub samples: usize,
+}
+
+impl Ohlc {
+    pub fn from_samples(samples: &[(i64, f64)]) -> Option<Self> {
+        let (first, last) = (samples.first()?, samples.last()?);
+        let mut high = *first;
+        let mut low = *first;
+        for sample in samples {
+            if sample.1 > high.1 {
+                high = *sample;
+            }
+            if sample.1 < low.1 {
+                low = *sample;
+            }
+        }
+        Some(Self {
+            open: first.1,
+            high,
+            low,
+            close: last.1,
+            start: first.0,
+            end: last.0,
+            samples: samples.len(),
+        })
+    }
+
+    // Percentage change from open to close, None when it opened at zero
+    pub fn change(&self) -> Option<f64> {
+        if self.open == 0.0 {
+            return None;
+        }
+        Some((self.close - self.open) / self.open * 100.0)
+    }
+}
+
+// USD prices recorded by the price monitor, appended to a JSON lines file and
+// kept in memory for the retention period
+pub struct PriceHistory {
+    file: Mutex<File>,
+    samples: Mutex<HashMap<String, VecDeque<(i64, f64)>>>,
+    retention_secs: i64,
+}
+
+impl PriceHistory {
+    // Loads the recorded samples and rewrites the file without the expired ones
+    pub fn new(data_dir: &str, retention_days: u64) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir, e))?;
+        let path = PathBuf::from(data_dir).join("price_history.jsonl");
+        let retention_secs = (retention_days * 24 * 60 * 60) as i64;
+        let cutoff = chrono::Utc::now().timestamp() - retention_secs;
+
+        let mut kept = Vec::new();
+        let mut samples: HashMap<String, VecDeque<(i64, f64)>> = HashMap::new();
+        if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+            for line in content.lines() {
+                let Ok(sample) = serde_json::from_str::<Sample>(line) else {
+                    warn!("Skipping unreadable price sample: {}", line);
+                    continue;
+                };
+                if sample.timestamp >= cutoff {
+                    samples.entry(sample.symbol.clone()).or_default().push_back((sample.timestamp, sample.price));
+                    kept.push(line);
+                }
+            }
+            let mut compacted = kept.join("\n");
+            if !compacted.is_empty() {
+                compacted.push('\n');
+            }
+            fs::write(&path, compacted).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            samples: Mutex::new(samples),
+            retention_secs,
+        })
+    }
+
+    pub fn record(&self, symbol: &str, timestamp: i64, price: f64) {
+        let sample = Sample { symbol: symbol.to_string(), timestamp, price };
+        match serde_json::to_string(&sample) {
+            Ok(line) => {
+                if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+                    error!("Failed to record price sample: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize price sample: {}", e),
+        }
+
+        let mut samples = self.samples.lock().unwrap();
+        let series = samples.entry(symbol.to_string()).or_default();
+        series.push_back((timestamp, price));
+        while series.front().is_some_and(|(t, _)| *t < timestamp - self.retention_secs) {
+            series.pop_front();
+        }
+    }
+
+    // Locally recorded samples since `since`, oldest first
+    pub fn recorded(&self, symbol: &str, since: i64) -> Vec<(i64, f64)> {
+        self.samples
+            .lock()
+            .unwrap()
+            .get(symbol)
+            .map(|series| series.iter().filter(|(t, _)| *t >= since).copied().collect())
+            .unwrap_or_default()
+    }
+
+    // Sparkline of the last 24 hours of recorded samples
+    pub fn sparkline_24h(&self, symbol: &str) -> Option<String> {
+        let symbol = price_source::find_symbol(symbol)?;
+        let since = chrono::Utc::now().timestamp() - 24 * 60 * 60;
+        chart::sparkline(&self.recorded(symbol, since), 24)
+    }
+
+    // Samples covering the last `period_secs`, from the local store when it reaches back far
+    // enough and from CoinGecko otherwise
+    pub async fn series(&self, symbol: &str, period_secs: i64) -> Result<Vec<(i64, f64)>, String> {
+        let symbol = price_source::find_symbol(symbol).ok_or("Unsupported symbol")?;
+        let now = chrono::Utc::now().timestamp();
+        if let Some(recorded) = self.local_series(symbol, now, period_secs) {
+            return Ok(recorded);
+        }
+
+        let since = now - period_secs;
+        let days = (period_secs as u64).div_ceil(24 * 60 * 60);
+        let remote = price_source::market_chart(symbol, days).await?;
+        Ok(remote.into_iter().filter(|(t, _)| *t >= since).collect())
+    }
+
+    // Recorded samples over the period up to `now`, if the first one is within a twentieth of the
+    // period from its start
+    fn local_series(&self, symbol: &str, now: i64, period_secs: i64) -> Option<Vec<(i64, f64)>> {
+        let since = now - period_secs;
+        let recorded = self.recorded(symbol, since - period_secs / 20);
+        let covers_period = recorded.first().is_some_and(|(t, _)| *t <= since + period_secs / 20);
+        covers_period.then(|| recorded.into_iter().filter(|(t, _)| *t >= since).collect())
+    }
+
+    // Candles of `interval_secs` over the last `count` intervals, oldest first. The last one is
+    // still forming, and intervals without any samples are skipped
+    pub async fn candles(&self, symbol: &str, interval_secs: i64, count: usize) -> Result<Vec<Ohlc>, String> {
+        let samples = self.series(symbol, interval_secs * count as i64).await?;
+        Ok(to_candles(&samples, interval_secs))
+    }
+}
+
+// Samples are grouped by the interval they fall in, counted from the unix epoch
+fn to_candles(samples: &[(i64, f64)], interval_secs: i64) -> Vec<Ohlc> {
+    samples
+        .chunk_by(|a, b| a.0 / interval_secs == b.0 / interval_secs)
+        .filter_map(Ohlc::from_samples)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    const NOW: i64 = 1_700_000_000;
+
+    fn history(name: &str) -> (PriceHistory, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("acubot-price-history-{}-{}", std::process::id(), name));
+        // Long enough to keep the fixed samples whatever the clock says
+        (PriceHistory::new(dir.to_str().unwrap(), 100_000).unwrap(), dir)
+    }
+
+    #[test]
+    fn ohlc_tracks_the_extremes_and_when_they_happened() {
+        let ohlc = Ohlc::from_samples(&[(10, 2.0), (20, 3.5), (30, 1.0), (40, 2.5)]).unwrap();
+        assert_eq!((ohlc.open, ohlc.close), (2.0, 2.5));
+        assert_eq!(ohlc.high, (20, 3.5));
+        assert_eq!(ohlc.low, (30, 1.0));
+        assert_eq!((ohlc.start, ohlc.end, ohlc.samples), (10, 40, 4));
+        assert_eq!(ohlc.change(), Some(25.0));
+        assert!(Ohlc::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn a_zero_open_has_no_change() {
+        let ohlc = Ohlc::from_samples(&[(10, 0.0), (20, 1.0)]).unwrap();
+        assert_eq!(ohlc.change(), None);
+    }
+
+    #[test]
+    fn local_samples_are_used_when_they_reach_back_far_enough() {
+        let (history, dir) = history("covered");
+        let period = 24 * HOUR;
+        // Within a twentieth of the period (72 minutes) of its start
+        history.record("ICP", NOW - period + HOUR, 5.0);
+        history.record("ICP", NOW - HOUR, 6.0);
+        assert_eq!(history.local_series("ICP", NOW, period), Some(vec![(NOW - period + HOUR, 5.0), (NOW - HOUR, 6.0)]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn local_samples_starting_too_late_are_not_used() {
+        let (history, dir) = history("late");
+        let period = 24 * HOUR;
+        history.record("ICP", NOW - period + 2 * HOUR, 5.0);
+        history.record("ICP", NOW - HOUR, 6.0);
+        assert_eq!(history.local_series("ICP", NOW, period), None);
+        assert_eq!(history.local_series("BTC", NOW, period), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn samples_just_before_the_period_count_as_coverage_but_are_left_out() {
+        let (history, dir) = history("early");
+        let period = 24 * HOUR;
+        history.record("ICP", NOW - period - HOUR / 2, 4.0);
+        history.record("ICP", NOW - HOUR, 6.0);
+        assert_eq!(history.local_series("ICP", NOW, period), Some(vec![(NOW - HOUR, 6.0)]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn candles_split_samples_by_interval() {
+        let samples = [
+            (0, 1.0),
+            (HOUR / 2, 3.0),
+            (HOUR, 2.0),
+            (HOUR + 10, 4.0),
+            // Nothing in the third hour
+            (3 * HOUR + 5, 5.0),
+        ];
+        let candles = to_candles(&samples, HOUR);
+        let summary: Vec<_> = candles.iter().map(|c| (c.start, c.open, c.high.1, c.low.1, c.close)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 1.0, 3.0, 1.0, 3.0),
+                (HOUR, 2.0, 4.0, 2.0, 4.0),
+                (3 * HOUR + 5, 5.0, 5.0, 5.0, 5.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+// CoinGecko ids and decimals for the symbols we can price. Chain-key tokens are priced as the
+// asset backing them.
+const COINS: &[(&str, &str, u32)] = &[
+    ("BTC", "bitcoin", 8),
+    ("ETH", "ethereum", 18),
+    ("ICP", "internet-computer", 8),
+    ("ckBTC", "bitcoin", 8),
+    ("ckETH", "ethereum", 18),
+    ("ckUSDC", "usd-coin", 6),
+    ("ckUSDT", "tether", 6),
+    ("CHAT", "openchat", 8),
+];
+
+// Fiat currencies CoinGecko publishes exchange rates for, with their minor unit decimals
+const FIATS: &[(&str, u32)] = &[
+    ("USD", 2),
+    ("EUR", 2),
+    ("GBP", 2),
+    ("CHF", 2),
+    ("JPY", 0),
+    ("CNY", 2),
+    ("CAD", 2),
+    ("AUD", 2),
+    ("INR", 2),
+    ("KRW", 0),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceQuote {
+    pub usd: f64,
+    // Percent change over the last 24 hours
+    pub change_24h: Option<f64>,
+}
+
+// Every symbol that can be priced
+pub fn symbols() -> impl Iterator<Item = &'static str> {
+    COINS.iter().map(|(s, _, _)| *s)
+}
+
+// Canonical spelling of a supported symbol, matched case-insensitively
+pub fn find_symbol(symbol: &str) -> Option<&'static str> {
+    COINS
+        .iter()
+        .find(|(s, _, _)| s.eq_ignore_ascii_case(symbol))
+        .map(|(s, _, _)| *s)
+}
+
+// Canonical spelling of a supported symbol or fiat currency code
+pub fn find_currency(code: &str) -> Option<&'static str> {
+    find_symbol(code).or_else(|| FIATS.iter().find(|(c, _)| c.eq_ignore_ascii_case(code)).map(|(c, _)| *c))
+}
+
+// Decimal places amounts of a symbol or fiat currency are expressed in
+pub fn decimals(code: &str) -> Option<u32> {
+    COINS
+        .iter()
+        .find(|(s, _, _)| s.eq_ignore_ascii_case(code))
+        .map(|(_, _, d)| *d)
+        .or_else(|| FIATS.iter().find(|(c, _)| c.eq_ignore_ascii_case(code)).map(|(_, d)| *d))
+}
+
+pub async fn usd_price(symbol: &str) -> Result<f64, String> {
+    usd_quote(symbol).await.map(|quote| quote.usd)
+}
+
+pub async fn usd_quote(symbol: &str) -> Result<PriceQuote, String> {
+    usd_quotes(&[symbol])
+        .await?
+        .into_values()
+        .next()
+        .ok_or_else(|| "Failed to parse price data".to_string())
+}
+
+// Prices for several symbols in one request, keyed by canonical symbol
+pub async fn usd_quotes(symbols: &[&str]) -> Result<HashMap<&'static str, PriceQuote>, String> {
+    if symbols.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut coins = Vec::new();
+    for symbol in symbols {
+        let (symbol, id, _) = COINS
+            .iter()
+            .find(|(s, _, _)| s.eq_ignore_ascii_case(symbol))
+            .ok_or_else(|| format!("Unsupported symbol {}", symbol))?;
+        coins.push((*symbol, *id));
+    }
+    let mut ids: Vec<&str> = coins.iter().map(|(_, id)| *id).collect();
+    ids.sort();
+    ids.dedup();
+
+    let url = format!(
+        "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd&include_24hr_change=true",
+        ids.join(",")
+    );
+    let data: serde_json::Value = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch price data".to_string())?
+        .json()
+        .await
+        .map_err(|_| "Failed to parse API response".to_string())?;
+
+    let mut quotes = HashMap::new();
+    for (symbol, id) in coins {
+        if let Some(usd) = data[id]["usd"].as_f64() {
+            quotes.insert(symbol, PriceQuote { usd, change_24h: data[id]["usd_24h_change"].as_f64() });
+        }
+    }
+    Ok(quotes)
+}
+
+// USD value of one unit of a symbol or fiat currency
+pub async fn usd_value(code: &str) -> Result<f64, String> {
+    if find_symbol(code).is_some() {
+        return usd_price(code).await;
+    }
+    let (fiat, _) = FIATS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .ok_or_else(|| format!("Unsupported currency {}", code))?;
+    if *fiat == "USD" {
+        return Ok(1.0);
+    }
+
+    // Exchange rates are quoted per BTC, so USD per unit is the ratio of the two
+    let data: serde_json::Value = reqwest::get("https://api.coingecko.com/api/v3/exchange_rates")
+        .await
+        .map_err(|_| "Failed to fetch exchange rates".to_string())?
+        .json()
+        .await
+        .map_err(|_| "Failed to parse API response".to_string())?;
+    let usd = data["rates"]["usd"]["value"].as_f64();
+    let rate = data["rates"][fiat.to_lowercase()]["value"].as_f64();
+    match (usd, rate) {
+        (Some(usd), Some(rate)) if rate > 0.0 => Ok(usd / rate),
+        _ => Err(format!("No exchange rate for {}", fiat)),
+    }
+}
+
+// Units of `to` one unit of `from` is worth, crossed through USD
+pub async fn cross_rate(from: &str, to: &str) -> Result<f64, String> {
+    let (from_usd, to_usd) = tokio::try_join!(usd_value(from), usd_value(to))?;
+    if to_usd <= 0.0 {
+        return Err(format!("No price for {}", to));
+    }
+    Ok(from_usd / to_usd)
+}
+
+// USD price samples over the last `days` days as (unix seconds, price), oldest first.
+// CoinGecko returns 5 minute samples for a day, hourly up to 90 days and daily beyond.
+pub async fn market_chart(symbol: &str, days: u64) -> Result<Vec<(i64, f64)>, String> {
+    let (_, id, _) = COINS
+        .iter()
+        .find(|(s, _, _)| s.eq_ignore_ascii_case(symbol))
+        .ok_or_else(|| format!("Unsupported symbol {}", symbol))?;
+    let url = format!(
+        "https://api.coingecko.com/api/v3/coins/{}/market_chart?vs_currency=usd&days={}",
+        id,
+        days.max(1)
+    );
+    let data: serde_json::Value = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch price history".to_string())?
+        .json()
+        .await
+        .map_err(|_| "Failed to parse API response".to_string())?;
+
+    let prices = data["prices"].as_array().ok_or("Failed to parse price history")?;
+    Ok(prices
+        .iter()
+        .filter_map(|point| Some((point[0].as_f64()? as i64 / 1000, point[1].as_f64()?)))
+        .collect())
+}
@@ -0,0 +1,146 @@
+use crate::audit::{AuditLog, Holder};
+use crate::notifier::ChatNotifier;
+use crate::tokens::{self, Token};
+use crate::wallet::Wallet;
+use oc_bots_sdk::types::Chat;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+// Periodically compares the custodial balances implied by the audit log with
+// what the ledgers actually hold, and reports any shortfall to the admin chat
+pub struct Reconciler {
+    wallet: Arc<Wallet>,
+    audit: Arc<AuditLog>,
+    notifier: Arc<ChatNotifier>,
+    admin_chat: Option<Chat>,
+}
+
+impl Reconciler {
+    pub fn new(wallet: Arc<Wallet>, audit: Arc<AuditLog>, notifier: Arc<ChatNotifier>, admin_chat: Option<Chat>) -> Self {
+        Self {
+            wallet,
+            audit,
+            notifier,
+            admin_chat,
+        }
+    }
+
+    pub async fn start_monitoring(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                self.reconcile().await;
+            }
+        });
+    }
+
+    async fn reconcile(&self) {
+        let mut shortfalls = Vec::new();
+        let mut checked = 0;
+        for balance in self.audit.expected_balances() {
+            let Some(token) = tokens::find(&balance.token) else {
+                continue;
+            };
+
+            // No transfers may land between reading the ledger and the log
+            let _paused = self.wallet.pause_transfers().await;
+            let actual = match self.wallet.holder_balance(balance.holder, token).await {
+                Ok(actual) => actual as i128,
+                Err(e) => {
+                    warn!("Failed to read {} balance of {:?}: {}", token.symbol, balance.holder, e);
+                    continue;
+                }
+            };
+            let expected = self.audit.expected_balance(balance.holder, token.symbol);
+            checked += 1;
+
+            if actual < expected {
+                shortfalls.push(format!(
+                    "• {}: expected {} {}, ledger has {} {}",
+                    describe_holder(balance.holder),
+                    format_units(token, expected),
+                    token.symbol,
+                    format_units(token, actual),
+                    token.symbol
+                ));
+            } else if actual > expected {
+                // Deposits, which arrive without going through the bot, are only logged and then
+                // taken as the new expected balance
+                info!(
+                    "{:?} holds {} {} more than expected, taking it as the expected balance",
+                    balance.holder,
+                    format_units(token, actual - expected),
+                    token.symbol
+                );
+                if let Err(e) = self.audit.set_expected_balance(balance.holder, token.symbol, actual) {
+                    error!("Failed to update expected balance: {}", e);
+                }
+            }
+        }
+
+        let overdrafts: Vec<String> = match self.audit.take_overdrafts() {
+            Ok(overdrafts) => overdrafts
+                .iter()
+                .filter_map(|o| {
+                    let token = tokens::find(&o.token)?;
+                    Some(format!(
+                        "• {}: transfer with memo {} left the expected balance at {} {}",
+                        describe_holder(o.holder),
+                        o.memo,
+                        format_units(token, o.balance),
+                        token.symbol
+                    ))
+                })
+                .collect(),
+            Err(e) => {
+                error!("Failed to read overdrafts: {}", e);
+                Vec::new()
+            }
+        };
+
+        if shortfalls.is_empty() && overdrafts.is_empty() {
+            info!("Reconciled {} custodial balances", checked);
+            return;
+        }
+
+        let mut sections = Vec::new();
+        if !shortfalls.is_empty() {
+            sections.push(format!(
+                "🚨 {} custodial balance(s) below what the audit log expects:\n{}",
+                shortfalls.len(),
+                shortfalls.join("\n")
+            ));
+        }
+        if !overdrafts.is_empty() {
+            sections.push(format!(
+                "⚠️ {} transfer(s) sent more than the audit log expected the sender to hold:\n{}",
+                overdrafts.len(),
+                overdrafts.join("\n")
+            ));
+        }
+        let message = format!("Reconciliation report\n\n{}", sections.join("\n\n"));
+        error!("{}", message);
+        if let Some(chat) = &self.admin_chat {
+            self.notifier.send_text(chat, message);
+        }
+    }
+}
+
+fn describe_holder(holder: Holder) -> String {
+    match holder {
+        Holder::User(user) => format!("@UserId({})", user),
+        Holder::Escrow => "Escrow".to_string(),
+        Holder::External => "External".to_string(),
+    }
+}
+
+// Whole tokens for a signed amount of base units
+fn format_units(token: &Token, amount: i128) -> String {
+    let units = token.from_units(amount.unsigned_abs());
+    if amount < 0 {
+        format!("-{}", units)
+    } else {
+        units.to_string()
+    }
+}
@@ -0,0 +1,43 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// State kept in a JSON file under the data directory, rewritten on every update
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: Mutex<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    pub fn open(data_dir: &str, name: &str) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create {}: {}", data_dir, e))?;
+        let path = PathBuf::from(data_dir).join(name);
+        let data = if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+            serde_json::from_str(&content).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?
+        } else {
+            T::default()
+        };
+        Ok(Self {
+            path,
+            data: Mutex::new(data),
+        })
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.lock().unwrap())
+    }
+
+    // Apply a change and persist it, writing to a temporary file first so a crash can't truncate the store
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, String> {
+        let mut data = self.data.lock().unwrap();
+        let result = f(&mut data);
+        let content = serde_json::to_vec_pretty(&*data).map_err(|e| e.to_string())?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, content).map_err(|e| format!("Failed to write {:?}: {}", tmp_path, e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| format!("Failed to write {:?}: {}", self.path, e))?;
+        Ok(result)
+    }
+}
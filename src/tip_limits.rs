@@ -0,0 +1,239 @@
+use crate::config::TipLimit;
+use crate::storage::JsonStore;
+use crate::tokens::Token;
+use chrono::{DateTime, Utc};
+use oc_bots_sdk::types::UserId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+// How long a confirmation prompt stays open for the tip to be sent again
+const CONFIRM_WINDOW_SECS: i64 = 10 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SpendBook {
+    // UTC date the totals belong to, they reset when it changes
+    day: String,
+    // Base units tipped today, keyed by "user:SYMBOL"
+    spent: HashMap<String, u128>,
+    // Tips waiting to be sent again with confirm set, keyed by "user:SYMBOL:total" with when
+    // they were prompted for
+    #[serde(default)]
+    prompts: HashMap<String, i64>,
+}
+
+// Tips counted against a day's cap, given back with `release` if the transfer doesn't go through
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    // None when the token has no daily cap
+    day: Option<String>,
+}
+
+// Enforces the configured tip limits before anything reaches a ledger
+pub struct TipLimits {
+    limits: HashMap<String, TipLimit>,
+    store: JsonStore<SpendBook>,
+}
+
+impl TipLimits {
+    pub fn new(limits: HashMap<String, TipLimit>, data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            limits: limits.into_iter().map(|(symbol, limit)| (symbol.to_lowercase(), limit)).collect(),
+            store: JsonStore::open(data_dir, "tip_spend.json")?,
+        })
+    }
+
+    // Check a tip of `total` base units paid out in shares of `per_tip`, and count it against
+    // today's cap. Call `release` if the transfer doesn't go through.
+    pub fn reserve(
+        &self,
+        user: UserId,
+        token: &Token,
+        per_tip: u128,
+        total: u128,
+        confirmed: bool,
+    ) -> Result<Reservation, String> {
+        self.reserve_at(user, token, per_tip, total, confirmed, Utc::now())
+    }
+
+    fn reserve_at(
+        &self,
+        user: UserId,
+        token: &Token,
+        per_tip: u128,
+        total: u128,
+        confirmed: bool,
+        now: DateTime<Utc>,
+    ) -> Result<Reservation, String> {
+        let Some(limit) = self.limits.get(&token.symbol.to_lowercase()) else {
+            return Ok(Reservation { day: None });
+        };
+
+        if let Some(max_tip) = limit.max_tip {
+            if per_tip > token.to_units(max_tip) {
+                return Err(format!("The largest single tip is {} {}", max_tip, token.symbol));
+            }
+        }
+        // Large tips go through only when sent again with confirm set after the prompt, so
+        // setting it on the first attempt doesn't skip the step
+        if let Some(confirm_above) = limit.confirm_above {
+            if total > token.to_units(confirm_above) {
+                let key = format!("{}:{}", spend_key(user, token), total);
+                let prompted = self.store.update(|book| {
+                    book.prompts.retain(|_, at| now.timestamp() - *at <= CONFIRM_WINDOW_SECS);
+                    if confirmed && book.prompts.remove(&key).is_some() {
+                        return true;
+                    }
+                    book.prompts.insert(key, now.timestamp());
+                    false
+                })?;
+                if !prompted {
+                    return Err(format!(
+                        "Tips over {} {} need confirming, send the same tip again with confirm set to yes within {} minutes",
+                        confirm_above,
+                        token.symbol,
+                        CONFIRM_WINDOW_SECS / 60
+                    ));
+                }
+            }
+        }
+
+        let Some(daily_cap) = limit.daily_cap else {
+            return Ok(Reservation { day: None });
+        };
+        let cap = token.to_units(daily_cap);
+        let today = now.format("%Y-%m-%d").to_string();
+        let key = spend_key(user, token);
+        self.store.update(|book| {
+            if book.day != today {
+                book.day = today.clone();
+                book.spent.clear();
+            }
+            let spent = book.spent.entry(key).or_default();
+            if *spent + total > cap {
+                return Err(format!(
+                    "Daily tipping cap is {} {}, you have {} {} left today",
+                    daily_cap,
+                    token.symbol,
+                    token.from_units(cap.saturating_sub(*spent)),
+                    token.symbol
+                ));
+            }
+            *spent += total;
+            Ok(Reservation { day: Some(today) })
+        })?
+    }
+
+    // Give back part or all of a reservation for tips that failed. A reservation from an
+    // earlier day is already gone with that day's totals.
+    pub fn release(&self, user: UserId, token: &Token, reservation: &Reservation, amount: u128) {
+        let Some(day) = &reservation.day else {
+            return;
+        };
+        let key = spend_key(user, token);
+        let result = self.store.update(|book| {
+            if book.day != *day {
+                return;
+            }
+            if let Some(spent) = book.spent.get_mut(&key) {
+                *spent = spent.saturating_sub(amount);
+            }
+        });
+        if let Err(e) = result {
+            error!("Failed to update tip spend: {}", e);
+        }
+    }
+}
+
+fn spend_key(user: UserId, token: &Token) -> String {
+    format!("{}:{}", user, token.symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens;
+    use candid::Principal;
+
+    fn limits(limit: TipLimit) -> (TipLimits, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("acubot-limits-{}-{}", std::process::id(), next_dir()));
+        let limits = TipLimits::new(HashMap::from([("ICP".to_string(), limit)]), dir.to_str().unwrap()).unwrap();
+        (limits, dir)
+    }
+
+    // Tests run in parallel, each gets its own data directory
+    fn next_dir() -> u64 {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn user() -> UserId {
+        UserId::from(Principal::anonymous())
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn icp(amount: f64) -> u128 {
+        tokens::find("ICP").unwrap().to_units(amount)
+    }
+
+    #[test]
+    fn single_tips_over_the_maximum_are_rejected() {
+        let (limits, dir) = limits(TipLimit { max_tip: Some(5.0), daily_cap: None, confirm_above: None });
+        let token = tokens::find("ICP").unwrap();
+        assert!(limits.reserve(user(), token, icp(5.0), icp(5.0), false).is_ok());
+        assert!(limits.reserve(user(), token, icp(5.1), icp(5.1), false).is_err());
+        // A rain checks each share, not the total
+        assert!(limits.reserve(user(), token, icp(2.0), icp(20.0), false).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_daily_cap_resets_at_utc_midnight() {
+        let (limits, dir) = limits(TipLimit { max_tip: None, daily_cap: Some(10.0), confirm_above: None });
+        let token = tokens::find("ICP").unwrap();
+        let morning = at("2024-01-01T08:00:00Z");
+        assert!(limits.reserve_at(user(), token, icp(6.0), icp(6.0), false, morning).is_ok());
+        assert!(limits.reserve_at(user(), token, icp(4.0), icp(4.0), false, morning).is_ok());
+        assert!(limits.reserve_at(user(), token, icp(0.1), icp(0.1), false, morning).is_err());
+        assert!(limits.reserve_at(user(), token, icp(10.0), icp(10.0), false, at("2024-01-02T00:00:00Z")).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn releases_give_back_the_same_day_only() {
+        let (limits, dir) = limits(TipLimit { max_tip: None, daily_cap: Some(10.0), confirm_above: None });
+        let token = tokens::find("ICP").unwrap();
+        let reservation = limits.reserve_at(user(), token, icp(8.0), icp(8.0), false, at("2024-01-01T12:00:00Z")).unwrap();
+        limits.release(user(), token, &reservation, icp(8.0));
+        assert!(limits.reserve_at(user(), token, icp(10.0), icp(10.0), false, at("2024-01-01T13:00:00Z")).is_ok());
+
+        // Reserved before midnight and released after it
+        let late = limits.reserve_at(user(), token, icp(0.0), icp(0.0), false, at("2024-01-01T23:59:00Z")).unwrap();
+        limits.reserve_at(user(), token, icp(7.0), icp(7.0), false, at("2024-01-02T00:01:00Z")).unwrap();
+        limits.release(user(), token, &late, icp(7.0));
+        assert!(limits.reserve_at(user(), token, icp(3.1), icp(3.1), false, at("2024-01-02T00:02:00Z")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn large_tips_need_sending_again_with_confirm() {
+        let (limits, dir) = limits(TipLimit { max_tip: None, daily_cap: None, confirm_above: Some(20.0) });
+        let token = tokens::find("ICP").unwrap();
+        let now = at("2024-01-01T12:00:00Z");
+        // Setting confirm on the first attempt still prompts
+        assert!(limits.reserve_at(user(), token, icp(50.0), icp(50.0), true, now).is_err());
+        // A different amount isn't confirmed by that prompt
+        assert!(limits.reserve_at(user(), token, icp(40.0), icp(40.0), true, now).is_err());
+        assert!(limits.reserve_at(user(), token, icp(50.0), icp(50.0), true, now + chrono::Duration::minutes(1)).is_ok());
+        // The prompt is used up
+        assert!(limits.reserve_at(user(), token, icp(50.0), icp(50.0), true, now + chrono::Duration::minutes(2)).is_err());
+        // And expires
+        assert!(limits.reserve_at(user(), token, icp(40.0), icp(40.0), true, now + chrono::Duration::minutes(11)).is_err());
+        assert!(limits.reserve_at(user(), token, icp(20.0), icp(20.0), false, now).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
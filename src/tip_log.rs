@@ -0,0 +1,230 @@
+use crate::storage::JsonStore;
+use oc_bots_sdk::types::{Chat, UserId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TipRecord {
+    pub sender: UserId,
+    pub recipient: UserId,
+    pub token: String,
+    // In base units
+    pub amount: u128,
+    pub chat: Option<Chat>,
+    pub block_index: u64,
+    // Sent into escrow rather than straight to the recipient
+    pub escrowed: bool,
+    pub timestamp: i64,
+    // An escrowed tip that expired unclaimed and went back to the sender
+    #[serde(default)]
+    pub refunded: bool,
+}
+
+// One line of a leaderboard, totals are in base units
+pub struct LeaderboardEntry {
+    pub user: UserId,
+    pub token: String,
+    pub amount: u128,
+    pub count: usize,
+}
+
+// Every tip the bot has sent, kept for history, leaderboards and auditing
+pub struct TipLog {
+    store: JsonStore<Vec<TipRecord>>,
+}
+
+impl TipLog {
+    pub fn new(data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            store: JsonStore::open(data_dir, "tips.json")?,
+        })
+    }
+
+    pub fn record(&self, record: TipRecord) {
+        if let Err(e) = self.store.update(|records| records.push(record)) {
+            error!("Failed to record tip: {}", e);
+        }
+    }
+
+    // Flag an escrowed tip as returned to its sender, it no longer counts towards leaderboards
+    pub fn mark_refunded(&self, token: &str, block_index: u64) {
+        let result = self.store.update(|records| {
+            if let Some(record) = records
+                .iter_mut()
+                .find(|r| r.escrowed && r.token == token && r.block_index == block_index)
+            {
+                record.refunded = true;
+            }
+        });
+        if let Err(e) = result {
+            error!("Failed to mark tip refunded: {}", e);
+        }
+    }
+
+    // Most recent tips the user sent or received, newest first
+    pub fn history(&self, user: UserId, limit: usize) -> Vec<TipRecord> {
+        self.store.read(|records| {
+            records
+                .iter()
+                .rev()
+                .filter(|r| r.sender == user || r.recipient == user)
+                .take(limit)
+                .cloned()
+                .collect()
+        })
+    }
+
+    // Every tip the user sent or received between two unix timestamps, oldest first
+    pub fn between(&self, user: UserId, from: i64, to: i64) -> Vec<TipRecord> {
+        self.store.read(|records| {
+            records
+                .iter()
+                .filter(|r| (r.sender == user || r.recipient == user) && r.timestamp >= from && r.timestamp < to)
+                .cloned()
+                .collect()
+        })
+    }
+
+    // Top senders and top receivers in a chat since `since`, ranked per token
+    pub fn leaderboard(&self, chat: &Chat, since: i64, limit: usize) -> (Vec<LeaderboardEntry>, Vec<LeaderboardEntry>) {
+        self.store.read(|records| leaderboard(records, chat, since, limit))
+    }
+}
+
+fn leaderboard(
+    records: &[TipRecord],
+    chat: &Chat,
+    since: i64,
+    limit: usize,
+) -> (Vec<LeaderboardEntry>, Vec<LeaderboardEntry>) {
+    let mut sent: HashMap<(UserId, String), (u128, usize)> = HashMap::new();
+    let mut received: HashMap<(UserId, String), (u128, usize)> = HashMap::new();
+    for record in records
+        .iter()
+        .filter(|r| r.chat.as_ref() == Some(chat) && r.timestamp >= since && !r.refunded)
+    {
+        let total = sent.entry((record.sender, record.token.clone())).or_default();
+        total.0 += record.amount;
+        total.1 += 1;
+        let total = received.entry((record.recipient, record.token.clone())).or_default();
+        total.0 += record.amount;
+        total.1 += 1;
+    }
+    (rank(sent, limit), rank(received, limit))
+}
+
+fn rank(totals: HashMap<(UserId, String), (u128, usize)>, limit: usize) -> Vec<LeaderboardEntry> {
+    let mut entries: Vec<_> = totals
+        .into_iter()
+        .map(|((user, token), (amount, count))| LeaderboardEntry { user, token, amount, count })
+        .collect();
+    entries.sort_by(|a, b| a.token.cmp(&b.token).then(b.amount.cmp(&a.amount)));
+
+    // Keep the top `limit` for each token
+    let mut ranked = Vec::new();
+    let mut per_token = 0;
+    for entry in entries {
+        if ranked.last().is_some_and(|last: &LeaderboardEntry| last.token != entry.token) {
+            per_token = 0;
+        }
+        if per_token < limit {
+            per_token += 1;
+            ranked.push(entry);
+        }
+    }
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    const DAY: i64 = 24 * 60 * 60;
+    const NOW: i64 = 1_700_000_000;
+
+    fn user(id: u8) -> UserId {
+        UserId::from(Principal::from_slice(&[id]))
+    }
+
+    fn chat(canister: &str) -> Chat {
+        serde_json::from_str(&format!(r#"{{"Group":"{}"}}"#, canister)).unwrap()
+    }
+
+    fn group() -> Chat {
+        chat("rrkah-fqaaa-aaaaa-aaaaq-cai")
+    }
+
+    fn tip(sender: u8, recipient: u8, token: &str, amount: u128, days_ago: i64) -> TipRecord {
+        TipRecord {
+            sender: user(sender),
+            recipient: user(recipient),
+            token: token.to_string(),
+            amount,
+            chat: Some(group()),
+            block_index: 0,
+            escrowed: false,
+            timestamp: NOW - days_ago * DAY,
+            refunded: false,
+        }
+    }
+
+    fn summary(entries: &[LeaderboardEntry]) -> Vec<(UserId, &str, u128, usize)> {
+        entries.iter().map(|e| (e.user, e.token.as_str(), e.amount, e.count)).collect()
+    }
+
+    #[test]
+    fn senders_and_receivers_are_ranked_by_total() {
+        let records = vec![tip(1, 2, "ICP", 10, 1), tip(1, 3, "ICP", 10, 1), tip(2, 3, "ICP", 30, 1)];
+        let (senders, receivers) = leaderboard(&records, &group(), NOW - 7 * DAY, 5);
+        assert_eq!(summary(&senders), vec![(user(2), "ICP", 30, 1), (user(1), "ICP", 20, 2)]);
+        assert_eq!(summary(&receivers), vec![(user(3), "ICP", 40, 2), (user(2), "ICP", 10, 1)]);
+    }
+
+    #[test]
+    fn each_token_keeps_its_own_top_n() {
+        let records = vec![
+            tip(1, 9, "ICP", 30, 1),
+            tip(2, 9, "ICP", 20, 1),
+            tip(3, 9, "ICP", 10, 1),
+            tip(4, 9, "ckBTC", 5, 1),
+        ];
+        let (senders, _) = leaderboard(&records, &group(), NOW - 7 * DAY, 2);
+        assert_eq!(
+            summary(&senders),
+            vec![(user(1), "ICP", 30, 1), (user(2), "ICP", 20, 1), (user(4), "ckBTC", 5, 1)]
+        );
+    }
+
+    #[test]
+    fn other_chats_are_left_out() {
+        let mut elsewhere = tip(2, 3, "ICP", 50, 1);
+        elsewhere.chat = Some(chat("ryjl3-tyaaa-aaaaa-aaaba-cai"));
+        let mut direct = tip(2, 3, "ICP", 50, 1);
+        direct.chat = None;
+        let records = vec![tip(1, 3, "ICP", 10, 1), elsewhere, direct];
+        let (senders, _) = leaderboard(&records, &group(), NOW - 7 * DAY, 5);
+        assert_eq!(summary(&senders), vec![(user(1), "ICP", 10, 1)]);
+    }
+
+    #[test]
+    fn the_week_and_month_cover_their_own_tips() {
+        let records = vec![tip(1, 9, "ICP", 10, 2), tip(2, 9, "ICP", 20, 10), tip(3, 9, "ICP", 30, 40)];
+        let (week, _) = leaderboard(&records, &group(), NOW - 7 * DAY, 5);
+        assert_eq!(summary(&week), vec![(user(1), "ICP", 10, 1)]);
+        let (month, _) = leaderboard(&records, &group(), NOW - 30 * DAY, 5);
+        assert_eq!(summary(&month), vec![(user(2), "ICP", 20, 1), (user(1), "ICP", 10, 1)]);
+    }
+
+    #[test]
+    fn refunded_tips_dont_count() {
+        let mut refunded = tip(2, 3, "ICP", 50, 1);
+        refunded.escrowed = true;
+        refunded.refunded = true;
+        let records = vec![tip(1, 3, "ICP", 10, 1), refunded];
+        let (senders, receivers) = leaderboard(&records, &group(), NOW - 7 * DAY, 5);
+        assert_eq!(summary(&senders), vec![(user(1), "ICP", 10, 1)]);
+        assert_eq!(summary(&receivers), vec![(user(3), "ICP", 10, 1)]);
+    }
+}
@@ -0,0 +1,67 @@
+// Tokens the bot knows how to read from the IC ledgers
+#[derive(Debug, Clone, Copy)]
+pub struct Token {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub ledger: &'static str,
+    pub decimals: u32,
+}
+
+pub const TOKENS: &[Token] = &[
+    Token {
+        symbol: "ICP",
+        name: "Internet Computer",
+        ledger: "ryjl3-tyaaa-aaaaa-aaaba-cai",
+        decimals: 8,
+    },
+    Token {
+        symbol: "ckBTC",
+        name: "Chain-key Bitcoin",
+        ledger: "mxzaz-hqaaa-aaaar-qaada-cai",
+        decimals: 8,
+    },
+    Token {
+        symbol: "ckETH",
+        name: "Chain-key Ether",
+        ledger: "ss2fx-dyaaa-aaaar-qacoq-cai",
+        decimals: 18,
+    },
+    Token {
+        symbol: "ckUSDC",
+        name: "Chain-key USDC",
+        ledger: "xevnm-gaaaa-aaaar-qafnq-cai",
+        decimals: 6,
+    },
+    Token {
+        symbol: "ckUSDT",
+        name: "Chain-key USDT",
+        ledger: "cngnf-vqaaa-aaaar-qag4q-cai",
+        decimals: 6,
+    },
+    Token {
+        symbol: "CHAT",
+        name: "OpenChat",
+        ledger: "2ouva-viaaa-aaaaq-aaamq-cai",
+        decimals: 8,
+    },
+];
+
+pub fn find(symbol: &str) -> Option<&'static Token> {
+    TOKENS.iter().find(|t| t.symbol.eq_ignore_ascii_case(symbol))
+}
+
+pub fn find_by_ledger(ledger: &str) -> Option<&'static Token> {
+    TOKENS.iter().find(|t| t.ledger == ledger)
+}
+
+impl Token {
+    // Convert an amount in ledger base units into whole tokens
+    pub fn from_units(&self, units: u128) -> f64 {
+        units as f64 / 10f64.powi(self.decimals as i32)
+    }
+
+    // Convert whole tokens into ledger base units
+    pub fn to_units(&self, amount: f64) -> u128 {
+        (amount * 10f64.powi(self.decimals as i32)).round() as u128
+    }
+}
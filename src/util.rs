@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+// Parse a period such as "30m", "4h" or "7d"
+pub fn parse_period(period: &str) -> Option<Duration> {
+    let period = period.trim().to_lowercase();
+    let (split, _) = period.char_indices().last()?;
+    let (value, unit) = period.split_at(split);
+    let value = value.parse::<u64>().ok().filter(|v| *v > 0)?;
+    let seconds = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(value * seconds))
+}
+
+// Parse an amount such as "250", "0.01", "1.5k", "2m" or "10,000". The k, m and b suffixes
+// multiply by a thousand, million and billion; commas are read as thousands separators.
+pub fn parse_amount(amount: &str) -> Option<f64> {
+    let amount = amount.trim().replace([',', '_'], "").to_lowercase();
+    let (split, suffix) = amount.char_indices().last()?;
+    let (number, multiplier) = match suffix {
+        'k' => (&amount[..split], 1e3),
+        'm' => (&amount[..split], 1e6),
+        'b' => (&amount[..split], 1e9),
+        _ => (amount.as_str(), 1.0),
+    };
+    let value = number.parse::<f64>().ok()? * multiplier;
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+// Format an amount to at most `decimals` places without trailing zeros. f64 only holds about
+// 15 significant digits, so anything past 8 places would be noise.
+pub fn format_amount(amount: f64, decimals: u32) -> String {
+    let formatted = format!("{:.*}", decimals.min(8) as usize, amount);
+    if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_accept_suffixes_and_separators() {
+        assert_eq!(parse_amount("250"), Some(250.0));
+        assert_eq!(parse_amount(" 0.01 "), Some(0.01));
+        assert_eq!(parse_amount("1.5k"), Some(1500.0));
+        assert_eq!(parse_amount("2M"), Some(2_000_000.0));
+        assert_eq!(parse_amount("3b"), Some(3e9));
+        assert_eq!(parse_amount("10,000"), Some(10_000.0));
+        assert_eq!(parse_amount("1_000"), Some(1000.0));
+    }
+
+    #[test]
+    fn amounts_must_be_finite_and_positive() {
+        for amount in ["", "k", "abc", "0", "-5", "NaN", "inf", "-inf", "infinity", "1e400", "1e300b"] {
+            assert_eq!(parse_amount(amount), None, "{}", amount);
+        }
+    }
+
+    #[test]
+    fn amounts_format_without_trailing_zeros() {
+        assert_eq!(format_amount(1.5, 8), "1.5");
+        assert_eq!(format_amount(2.0, 8), "2");
+        assert_eq!(format_amount(0.123456789, 4), "0.1235");
+        // Capped at 8 places
+        assert_eq!(format_amount(0.123456789012, 18), "0.12345679");
+        assert_eq!(format_amount(100.0, 0), "100");
+    }
+
+    #[test]
+    fn periods_parse_each_unit() {
+        assert_eq!(parse_period("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_period("4H"), Some(Duration::from_secs(4 * 60 * 60)));
+        assert_eq!(parse_period("7d"), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_period("2w"), Some(Duration::from_secs(14 * 24 * 60 * 60)));
+        assert_eq!(parse_period("0d"), None);
+        assert_eq!(parse_period("7y"), None);
+        assert_eq!(parse_period(""), None);
+    }
+}
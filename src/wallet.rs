@@ -0,0 +1,329 @@
+use crate::audit::{AuditEntry, AuditLog, AuditStatus, Holder, TransferKind};
+use crate::ledger::{self, Account, TransferReceipt};
+use crate::storage::JsonStore;
+use crate::tokens::Token;
+use candid::Principal;
+use ic_agent::Agent;
+use oc_bots_sdk::types::{Chat, UserId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as SyncMutex};
+use tokio::sync::{Mutex, RwLock, RwLockWriteGuard};
+use tracing::warn;
+
+// Holds tips for users who haven't set up their wallet yet. User subaccounts
+// start with the principal's length (at most 29), so this can't collide with one.
+const ESCROW_SUBACCOUNT: [u8; 32] = *b"escrow\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+
+// ICRC-1 ledgers deduplicate transfers created within the last 24 hours
+const DEDUP_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// Custodial wallet, every user gets their own subaccount of the bot's principal
+pub struct Wallet {
+    agent: Agent,
+    principal: Principal,
+    // Smallest withdrawal, as a multiple of the token's ledger fee
+    min_withdrawal_fees: u32,
+    audit: Arc<AuditLog>,
+    // Transfers hold a read lock, reconciliation takes the write lock
+    transfers: RwLock<()>,
+    // Transfers out of one holder's subaccount run one at a time
+    holders: SyncMutex<HashMap<(Holder, &'static str), Arc<Mutex<()>>>>,
+    // created_at_time first used with each memo, so a retry sends an identical transfer
+    created_at: JsonStore<HashMap<String, u64>>,
+}
+
+impl Wallet {
+    pub fn new(agent: Agent, min_withdrawal_fees: u32, audit: Arc<AuditLog>, data_dir: &str) -> Result<Self, String> {
+        let principal = agent.get_principal()?;
+        Ok(Self {
+            agent,
+            principal,
+            min_withdrawal_fees,
+            audit,
+            transfers: RwLock::new(()),
+            holders: SyncMutex::new(HashMap::new()),
+            created_at: JsonStore::open(data_dir, "transfer_times.json")?,
+        })
+    }
+
+    // Where a user deposits funds
+    pub fn user_account(&self, user: UserId) -> Account {
+        self.holder_account(Holder::User(user))
+    }
+
+    pub async fn balance(&self, user: UserId, token: &Token) -> Result<u128, String> {
+        self.holder_balance(Holder::User(user), token).await
+    }
+
+    // On-chain balance of any of the bot's custodial subaccounts
+    pub async fn holder_balance(&self, holder: Holder, token: &Token) -> Result<u128, String> {
+        ledger::balance_of(&self.agent, token, &self.holder_account(holder)).await
+    }
+
+    pub async fn fee(&self, token: &Token) -> Result<u128, String> {
+        ledger::fee(&self.agent, token).await
+    }
+
+    // The receipt of a transfer that already completed with this memo, e.g. on an earlier
+    // attempt at a retried command, and who it went to
+    pub fn completed_transfer(&self, memo: &[u8]) -> Option<(Holder, TransferReceipt)> {
+        let transfer = self.audit.completed(&memo_hex(memo))?;
+        let receipt = TransferReceipt {
+            block_index: transfer.block_index,
+            fee: transfer.fee,
+            duplicate: true,
+        };
+        Some((transfer.to, receipt))
+    }
+
+    // Have the user's subaccount reconciled from now on, deposits can arrive before any transfer
+    pub fn track(&self, user: UserId) -> Result<(), String> {
+        self.audit.track(Holder::User(user))
+    }
+
+    // Hold off new transfers while balances are reconciled
+    pub async fn pause_transfers(&self) -> RwLockWriteGuard<'_, ()> {
+        self.transfers.write().await
+    }
+
+    // Move `amount` base units from one user's subaccount to another's, the sender pays the fee
+    pub async fn tip(
+        &self,
+        from: UserId,
+        to: UserId,
+        token: &Token,
+        amount: u128,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TransferReceipt, String> {
+        let fee = self.fee(token).await?;
+        self.transfer(TransferKind::Tip, Holder::User(from), Holder::User(to), None, token, amount, fee, chat, memo)
+            .await
+    }
+
+    // Move `amount` base units from a user's subaccount into escrow, the sender pays the fee
+    pub async fn to_escrow(
+        &self,
+        from: UserId,
+        token: &Token,
+        amount: u128,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TransferReceipt, String> {
+        let fee = self.fee(token).await?;
+        self.transfer(TransferKind::ToEscrow, Holder::User(from), Holder::Escrow, None, token, amount, fee, chat, memo)
+            .await
+    }
+
+    // Release `amount` base units held in escrow to a user, the fee comes out of the amount
+    pub async fn from_escrow(
+        &self,
+        to: UserId,
+        token: &Token,
+        amount: u128,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TransferReceipt, String> {
+        let fee = self.fee(token).await?;
+        if amount <= fee {
+            return Err("Escrowed amount doesn't cover the ledger fee".to_string());
+        }
+        self.transfer(TransferKind::FromEscrow, Holder::Escrow, Holder::User(to), None, token, amount - fee, fee, chat, memo)
+            .await
+    }
+
+    // Send `amount` base units out of a user's subaccount, the fee comes out of the amount
+    pub async fn withdraw(
+        &self,
+        user: UserId,
+        token: &Token,
+        amount: u128,
+        to: Account,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TransferReceipt, String> {
+        // A retry of a withdrawal that went through must not be checked against the balance it spent
+        if let Some((_, receipt)) = self.completed_transfer(&memo) {
+            return Ok(receipt);
+        }
+        let fee = self.fee(token).await?;
+        let minimum = fee * self.min_withdrawal_fees.max(1) as u128;
+        if amount < minimum || amount <= fee {
+            return Err(format!(
+                "Minimum withdrawal is {} {}",
+                token.from_units(minimum.max(fee + 1)),
+                token.symbol
+            ));
+        }
+        let balance = self.balance(user, token).await?;
+        if amount > balance {
+            return Err(format!(
+                "Insufficient funds, balance is {} {}",
+                token.from_units(balance),
+                token.symbol
+            ));
+        }
+        self.transfer(TransferKind::Withdraw, Holder::User(user), Holder::External, Some(to), token, amount - fee, fee, chat, memo)
+            .await
+    }
+
+    fn holder_account(&self, holder: Holder) -> Account {
+        Account {
+            owner: self.principal,
+            subaccount: holder_subaccount(holder),
+        }
+    }
+
+    // Every ledger transfer goes through here so it ends up in the audit log
+    #[allow(clippy::too_many_arguments)]
+    async fn transfer(
+        &self,
+        kind: TransferKind,
+        from: Holder,
+        to: Holder,
+        to_account: Option<Account>,
+        token: &Token,
+        amount: u128,
+        fee: u128,
+        chat: Option<Chat>,
+        memo: Vec<u8>,
+    ) -> Result<TransferReceipt, String> {
+        let _guard = self.transfers.read().await;
+        let holder = self.holders.lock().unwrap().entry((from, token.symbol)).or_default().clone();
+        let _holder_guard = holder.lock().await;
+
+        // Deposits only reach the expected balance at reconciliation, fold them in first so the
+        // debit isn't taken for an overdraft
+        match self.holder_balance(from, token).await {
+            Ok(balance) => {
+                if let Err(e) = self.audit.credit_deposits(from, token.symbol, balance as i128) {
+                    warn!("Failed to credit deposits of {:?}: {}", from, e);
+                }
+            }
+            Err(e) => warn!("Failed to read {} balance of {:?}: {}", token.symbol, from, e),
+        }
+
+        let to_account = to_account.unwrap_or_else(|| self.holder_account(to));
+        let memo_hex = memo_hex(&memo);
+        let created_at_time = self.created_at_time(&memo_hex)?;
+        let result = ledger::transfer(
+            &self.agent,
+            token,
+            holder_subaccount(from),
+            to_account.clone(),
+            amount,
+            fee,
+            Some(memo),
+            Some(created_at_time),
+        )
+        .await;
+
+        self.audit.record(AuditEntry {
+            timestamp: chrono::Utc::now().timestamp(),
+            kind,
+            from,
+            to,
+            destination: to_account.to_string(),
+            chat,
+            token: token.symbol.to_string(),
+            amount,
+            fee: result.as_ref().map_or(fee, |r| r.fee),
+            block_index: result.as_ref().ok().map(|r| r.block_index),
+            memo: memo_hex,
+            status: match &result {
+                Ok(receipt) if receipt.duplicate => AuditStatus::Duplicate,
+                Ok(_) => AuditStatus::Completed,
+                Err(e) => AuditStatus::Failed(e.clone()),
+            },
+        });
+        result
+    }
+
+    // Reuse the timestamp of an earlier attempt with the same memo while the ledger still remembers it
+    fn created_at_time(&self, memo_hex: &str) -> Result<u64, String> {
+        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+        self.created_at.update(|times| {
+            times.retain(|_, time| now.saturating_sub(*time) < DEDUP_WINDOW_NANOS);
+            *times.entry(memo_hex.to_string()).or_insert(now)
+        })
+    }
+}
+
+// Memos identify the operation a transfer belongs to, so retrying that operation produces
+// the same memo. Commands use the id of the bot's reply message, which OpenChat keeps
+// when it retries a command, plus the recipient when one command pays several users.
+pub fn command_memo(message_id: u128, recipient: Option<UserId>) -> Vec<u8> {
+    let mut memo = vec![b'c'];
+    memo.extend_from_slice(&message_id.to_be_bytes());
+    if let Some(user) = recipient {
+        memo.extend_from_slice(Principal::from(user).as_slice());
+    }
+    // ICRC-1 memos are at most 32 bytes
+    memo.truncate(32);
+    memo
+}
+
+// Paying out an escrowed tip is keyed by the escrow entry alone, so a claim and a refund
+// of the same tip share a memo
+pub fn escrow_memo(escrow_id: u64) -> Vec<u8> {
+    let mut memo = vec![b'e'];
+    memo.extend_from_slice(&escrow_id.to_be_bytes());
+    memo
+}
+
+fn memo_hex(memo: &[u8]) -> String {
+    memo.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn holder_subaccount(holder: Holder) -> Option<Vec<u8>> {
+    match holder {
+        Holder::User(user) => Some(user_subaccount(user)),
+        Holder::Escrow => Some(ESCROW_SUBACCOUNT.to_vec()),
+        Holder::External => None,
+    }
+}
+
+// The usual principal-to-subaccount mapping: length byte, principal bytes, zero padding
+fn user_subaccount(user: UserId) -> Vec<u8> {
+    let principal = Principal::from(user);
+    let bytes = principal.as_slice();
+    let mut subaccount = vec![0u8; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..1 + bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u8) -> UserId {
+        UserId::from(Principal::from_slice(&[id; 10]))
+    }
+
+    #[test]
+    fn command_memos_are_stable_per_command_and_recipient() {
+        assert_eq!(command_memo(42, None), command_memo(42, None));
+        assert_ne!(command_memo(42, None), command_memo(43, None));
+        assert_eq!(command_memo(42, Some(user(1))), command_memo(42, Some(user(1))));
+        assert_ne!(command_memo(42, Some(user(1))), command_memo(42, Some(user(2))));
+        assert!(command_memo(u128::MAX, Some(user(1))).len() <= 32);
+    }
+
+    #[test]
+    fn escrow_payouts_share_one_memo_per_entry() {
+        assert_eq!(escrow_memo(5), escrow_memo(5));
+        assert_ne!(escrow_memo(5), escrow_memo(6));
+        assert_ne!(escrow_memo(5)[0], command_memo(5, None)[0]);
+    }
+
+    #[test]
+    fn user_subaccounts_are_length_prefixed_principals() {
+        let subaccount = user_subaccount(user(3));
+        assert_eq!(subaccount.len(), 32);
+        assert_eq!(subaccount[0], 10);
+        assert_eq!(&subaccount[1..11], &[3; 10]);
+        assert!(subaccount[11..].iter().all(|b| *b == 0));
+        assert_ne!(subaccount, ESCROW_SUBACCOUNT.to_vec());
+    }
+}
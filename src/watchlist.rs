@@ -0,0 +1,135 @@
+use crate::price_history::PriceHistory;
+use crate::price_source;
+use crate::storage::JsonStore;
+use oc_bots_sdk::types::UserId;
+use std::collections::HashMap;
+
+const MAX_SYMBOLS: usize = 20;
+
+// Symbols each user wants to keep an eye on, shared by /watch and /price watchlist
+pub struct Watchlists {
+    // Keyed by user id text
+    store: JsonStore<HashMap<String, Vec<String>>>,
+}
+
+impl Watchlists {
+    pub fn new(data_dir: &str) -> Result<Self, String> {
+        Ok(Self {
+            store: JsonStore::open(data_dir, "watchlists.json")?,
+        })
+    }
+
+    pub fn symbols(&self, user: UserId) -> Vec<String> {
+        self.store.read(|lists| lists.get(&user.to_string()).cloned().unwrap_or_default())
+    }
+
+    // Returns the symbol as it's stored
+    pub fn add(&self, user: UserId, symbol: &str) -> Result<&'static str, String> {
+        let symbol = price_source::find_symbol(symbol).ok_or("Unsupported symbol")?;
+        self.store.update(|lists| {
+            let list = lists.entry(user.to_string()).or_default();
+            if list.iter().any(|s| s == symbol) {
+                return Err(format!("{} is already on your watchlist", symbol));
+            }
+            if list.len() >= MAX_SYMBOLS {
+                return Err(format!("Your watchlist can hold at most {} symbols", MAX_SYMBOLS));
+            }
+            list.push(symbol.to_string());
+            Ok(symbol)
+        })?
+    }
+
+    // Returns the symbol as it was stored, or None if it wasn't on the list
+    pub fn remove(&self, user: UserId, symbol: &str) -> Result<Option<String>, String> {
+        self.store.update(|lists| {
+            let list = lists.get_mut(&user.to_string())?;
+            let index = list.iter().position(|s| s.eq_ignore_ascii_case(symbol))?;
+            Some(list.remove(index))
+        })
+    }
+}
+
+// Current price and 24h change for each symbol, as a monospace table, with a
+// sparkline column when recorded history is passed in
+pub async fn price_table(symbols: &[String], history: Option<&PriceHistory>) -> Result<String, String> {
+    let symbols: Vec<&str> = symbols.iter().map(|s| s.as_str()).collect();
+    let quotes = price_source::usd_quotes(&symbols).await?;
+
+    let mut table = format!("{:<8} {:>14} {:>9}", "Symbol", "Price", "24h");
+    if history.is_some() {
+        table.push_str("  Last 24h");
+    }
+    table.push('\n');
+    for symbol in symbols {
+        let line = match price_source::find_symbol(symbol).and_then(|s| quotes.get(s)) {
+            Some(quote) => format!(
+                "{:<8} {:>14} {:>9}",
+                symbol,
+                format!("${:.4}", quote.usd),
+                quote.change_24h.map_or("n/a".to_string(), |c| format!("{:+.2}%", c))
+            ),
+            None => format!("{:<8} {:>14} {:>9}", symbol, "n/a", "n/a"),
+        };
+        table.push_str(&line);
+        if let Some(history) = history {
+            table.push_str("  ");
+            table.push_str(&history.sparkline_24h(symbol).unwrap_or_default());
+        }
+        table.push('\n');
+    }
+    Ok(format!("```\n{}```", table))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn watchlists(name: &str) -> (Watchlists, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("acubot-watchlist-{}-{}", std::process::id(), name));
+        (Watchlists::new(dir.to_str().unwrap()).unwrap(), dir)
+    }
+
+    fn user() -> UserId {
+        UserId::from(Principal::anonymous())
+    }
+
+    #[test]
+    fn symbols_are_stored_in_their_canonical_spelling() {
+        let (watchlists, dir) = watchlists("canonical");
+        assert_eq!(watchlists.add(user(), "ckusdc"), Ok("ckUSDC"));
+        assert_eq!(watchlists.add(user(), "icp"), Ok("ICP"));
+        assert_eq!(watchlists.symbols(user()), vec!["ckUSDC", "ICP"]);
+        assert!(watchlists.add(user(), "DOGE").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_symbol_can_only_be_added_once() {
+        let (watchlists, dir) = watchlists("duplicate");
+        watchlists.add(user(), "ICP").unwrap();
+        assert!(watchlists.add(user(), "Icp").is_err());
+        assert_eq!(watchlists.symbols(user()), vec!["ICP"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn full_watchlists_are_rejected() {
+        let (watchlists, dir) = watchlists("full");
+        let full = (0..MAX_SYMBOLS).map(|i| format!("T{}", i)).collect();
+        watchlists.store.update(|lists| lists.insert(user().to_string(), full)).unwrap();
+        assert!(watchlists.add(user(), "ICP").is_err());
+        assert_eq!(watchlists.symbols(user()).len(), MAX_SYMBOLS);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn remove_returns_the_stored_symbol() {
+        let (watchlists, dir) = watchlists("remove");
+        watchlists.add(user(), "ckUSDC").unwrap();
+        assert_eq!(watchlists.remove(user(), "CKUSDC"), Ok(Some("ckUSDC".to_string())));
+        assert!(watchlists.symbols(user()).is_empty());
+        assert_eq!(watchlists.remove(user(), "ckUSDC"), Ok(None));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}